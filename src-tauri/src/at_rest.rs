@@ -0,0 +1,187 @@
+use std::sync::Mutex;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use sha2::{Digest, Sha256};
+
+/// Starts every encrypted file, anything else is read as plaintext
+pub const MAGIC: &[u8] = b"VDENC2\0";
+/// Starts files encrypted before keys were salted, still read but never written
+const LEGACY_MAGIC: &[u8] = b"VDENC1\0";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Turns the configured secret into a cipher key with Argon2, salted so that guesses can't be
+/// shared between files or precomputed
+fn derive_key(secret: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(secret.as_bytes(), salt, &mut key)
+        .expect("argon2 rejected a 16 bytes salt or a 32 bytes key");
+    key
+}
+
+/// Key of `LEGACY_MAGIC` files, a plain hash of the secret
+fn legacy_key(secret: &str) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&Sha256::digest(secret.as_bytes()));
+    key
+}
+
+/// The configured secret. Deriving a key is slow on purpose, so the key of the salt this
+/// process writes with and the last one read are kept.
+pub struct Secret {
+    secret: String,
+    salt: [u8; SALT_LEN],
+    key: [u8; 32],
+    last_read: Mutex<Option<(Vec<u8>, [u8; 32])>>,
+}
+
+impl Secret {
+    pub fn new(secret: &str) -> Self {
+        let salt: [u8; SALT_LEN] = rand::random();
+        Self {
+            secret: secret.to_owned(),
+            key: derive_key(secret, &salt),
+            salt,
+            last_read: Mutex::new(None),
+        }
+    }
+
+    fn key_for(&self, salt: &[u8]) -> [u8; 32] {
+        if salt == self.salt {
+            return self.key;
+        }
+        let mut last_read = self.last_read.lock().expect("key cache lock is poisoned");
+        match &*last_read {
+            Some((last_salt, key)) if last_salt == salt => *key,
+            _ => {
+                let key = derive_key(&self.secret, salt);
+                *last_read = Some((salt.to_vec(), key));
+                key
+            }
+        }
+    }
+}
+
+/// Encrypts `plaintext` as `MAGIC`, the salt of the key, a random nonce, then the ciphertext
+/// and its tag
+pub fn seal(secret: &Secret, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&secret.key))
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| "cannot encrypt storage".to_owned())?;
+    let mut sealed = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(MAGIC);
+    sealed.extend_from_slice(&secret.salt);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypts what `seal` produced, plaintext content being returned as is
+pub fn open(secret: Option<&Secret>, content: &[u8]) -> Result<Vec<u8>, String> {
+    let (key, sealed) = match (
+        content.strip_prefix(MAGIC),
+        content.strip_prefix(LEGACY_MAGIC),
+    ) {
+        (None, None) => return Ok(content.to_vec()),
+        (Some(sealed), _) => {
+            let secret = secret.ok_or("storage is encrypted but no key is configured")?;
+            if sealed.len() < SALT_LEN {
+                return Err("encrypted storage is truncated".to_owned());
+            }
+            let (salt, sealed) = sealed.split_at(SALT_LEN);
+            (secret.key_for(salt), sealed)
+        }
+        (None, Some(sealed)) => {
+            let secret = secret.ok_or("storage is encrypted but no key is configured")?;
+            (legacy_key(&secret.secret), sealed)
+        }
+    };
+    if sealed.len() < NONCE_LEN {
+        return Err("encrypted storage is truncated".to_owned());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "cannot decrypt storage, the key is wrong or the file was altered".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_content_opens_with_the_same_key() {
+        let secret = Secret::new("correct horse battery staple");
+        let sealed = seal(&secret, b"private votes").unwrap();
+        assert!(sealed.starts_with(MAGIC));
+        assert!(!sealed
+            .windows(b"private votes".len())
+            .any(|w| w == b"private votes"));
+        assert_eq!(open(Some(&secret), &sealed).unwrap(), b"private votes");
+        // another run salts its key differently, and still reads it
+        let restarted = Secret::new("correct horse battery staple");
+        assert_ne!(restarted.salt, secret.salt);
+        assert_eq!(open(Some(&restarted), &sealed).unwrap(), b"private votes");
+    }
+
+    #[test]
+    fn sealing_twice_gives_different_content() {
+        let secret = Secret::new("secret");
+        assert_ne!(
+            seal(&secret, b"votes").unwrap(),
+            seal(&secret, b"votes").unwrap()
+        );
+    }
+
+    #[test]
+    fn keys_are_salted() {
+        assert_ne!(
+            derive_key("secret", &[0; SALT_LEN]),
+            derive_key("secret", &[1; SALT_LEN])
+        );
+        assert_ne!(derive_key("secret", &[0; SALT_LEN]), legacy_key("secret"));
+    }
+
+    #[test]
+    fn wrong_or_missing_keys_fail() {
+        let sealed = seal(&Secret::new("secret"), b"private votes").unwrap();
+        assert!(open(Some(&Secret::new("other secret")), &sealed).is_err());
+        assert!(open(None, &sealed).is_err());
+    }
+
+    #[test]
+    fn altered_or_truncated_content_fails() {
+        let secret = Secret::new("secret");
+        let mut sealed = seal(&secret, b"private votes").unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(open(Some(&secret), &sealed).is_err());
+        assert!(open(Some(&secret), &sealed[..MAGIC.len() + SALT_LEN + 4]).is_err());
+        assert!(open(Some(&secret), &sealed[..MAGIC.len() + 4]).is_err());
+    }
+
+    #[test]
+    fn files_with_unsalted_keys_are_still_read() {
+        let nonce = [7u8; NONCE_LEN];
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&legacy_key("secret")))
+            .encrypt(Nonce::from_slice(&nonce), &b"older votes"[..])
+            .unwrap();
+        let sealed = [LEGACY_MAGIC, &nonce[..], &ciphertext[..]].concat();
+        assert_eq!(
+            open(Some(&Secret::new("secret")), &sealed).unwrap(),
+            b"older votes"
+        );
+        assert!(open(Some(&Secret::new("other secret")), &sealed).is_err());
+    }
+
+    #[test]
+    fn plaintext_is_read_as_is() {
+        let secret = Secret::new("secret");
+        assert_eq!(open(Some(&secret), b"[]").unwrap(), b"[]");
+        assert_eq!(open(None, b"[]").unwrap(), b"[]");
+    }
+}
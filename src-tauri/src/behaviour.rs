@@ -1,23 +1,81 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
 use libp2p::{
   NetworkBehaviour,
   mdns::{Mdns,MdnsEvent,MdnsConfig},
-  floodsub::{Floodsub,FloodsubEvent},
-  PeerId
+  gossipsub::{Gossipsub,GossipsubConfigBuilder,GossipsubEvent,GossipsubMessage,MessageAuthenticity,MessageId},
+  ping::{Ping,PingConfig,PingEvent},
+  swarm::toggle::Toggle,
+  identity::Keypair
 };
 
-/// A combined NetworkBehaviour that supports both MDNS and Floodsub
+/// A combined NetworkBehaviour that supports MDNS, Gossipsub and Ping
 #[derive(NetworkBehaviour)]
 #[behaviour(out_event = "BehaviourEvent", event_process = false)]
 pub struct Behaviour {
-  pub mdns: Mdns,
-  pub floodsub: Floodsub,
+  /// Disabled in local-only mode, so the node never advertises itself on the LAN
+  pub mdns: Toggle<Mdns>,
+  pub gossipsub: Gossipsub,
+  pub ping: Ping,
+}
+
+/// Largest payload accepted, anything larger is dropped before being parsed
+pub const MAX_MESSAGE_BYTES: usize = 256 * 1024;
+/// What gossipsub adds around a payload: author, sequence number, topic, signature and key
+const ENVELOPE_BYTES: usize = 4 * 1024;
+
+/// Seconds between two mDNS queries, 5 minutes by default. Lower it to notice new peers sooner
+/// at the cost of more multicast traffic.
+pub const MDNS_QUERY_INTERVAL_ENV: &str = "VOTINGDAPP_MDNS_QUERY_INTERVAL_SECS";
+/// Seconds a discovered peer stays known without being seen again, 6 minutes by default.
+/// Lower it to forget peers that left sooner.
+pub const MDNS_TTL_ENV: &str = "VOTINGDAPP_MDNS_TTL_SECS";
+
+fn env_secs(name: &str) -> Option<Duration> {
+  env::var(name).ok()?.parse().ok().map(Duration::from_secs)
+}
+
+/// libp2p's defaults, overridden by `MDNS_QUERY_INTERVAL_ENV` and `MDNS_TTL_ENV` when set
+pub fn mdns_config_from_env() -> MdnsConfig {
+  let default = MdnsConfig::default();
+  MdnsConfig {
+    query_interval: env_secs(MDNS_QUERY_INTERVAL_ENV).unwrap_or(default.query_interval),
+    ttl: env_secs(MDNS_TTL_ENV).unwrap_or(default.ttl),
+  }
 }
 
 impl Behaviour {
-  pub async fn new(peer_id: PeerId) -> Self {
+  pub async fn new(keys: Keypair) -> Self {
+    Self::with_mdns_config(keys, Some(mdns_config_from_env())).await
+  }
+
+  /// Without an mDNS config, peers are only found by dialing them
+  pub async fn with_mdns_config(keys: Keypair, mdns_config: Option<MdnsConfig>) -> Self {
+    // Messages are identified by their author and content rather than by sequence number,
+    // so a peer repeating the same request or response within the duplicate cache window
+    // (60s by default) is only delivered once.
+    let config = GossipsubConfigBuilder::default()
+      .message_id_fn(|message: &GossipsubMessage| {
+        let mut hasher = DefaultHasher::new();
+        message.source.hash(&mut hasher);
+        message.data.hash(&mut hasher);
+        MessageId::from(hasher.finish().to_string())
+      })
+      // the default of 64 KiB can't fit a few hundred signed votes
+      .max_transmit_size(MAX_MESSAGE_BYTES + ENVELOPE_BYTES)
+      .build()
+      .unwrap();
     Self {
-      mdns: Mdns::new(MdnsConfig::default()).await.unwrap(),
-      floodsub: Floodsub::new(peer_id)
+      mdns: Toggle::from(match mdns_config {
+        Some(config) => Some(Mdns::new(config).await.unwrap()),
+        None => None,
+      }),
+      gossipsub: Gossipsub::new(MessageAuthenticity::Signed(keys), config).unwrap(),
+      // keeps connections open between pings, so a dead peer is noticed by its failures
+      ping: Ping::new(PingConfig::new().with_keep_alive(true))
     }
   }
 }
@@ -26,7 +84,8 @@ impl Behaviour {
 #[derive(Debug)]
 pub enum BehaviourEvent {
   MdnsEvent(MdnsEvent),
-  FloodsubEvent(FloodsubEvent),
+  GossipsubEvent(GossipsubEvent),
+  PingEvent(PingEvent),
 }
 
 impl From<MdnsEvent> for BehaviourEvent {
@@ -34,8 +93,13 @@ impl From<MdnsEvent> for BehaviourEvent {
     BehaviourEvent::MdnsEvent(event)
   }
 }
-impl From<FloodsubEvent> for BehaviourEvent {
-  fn from(event: FloodsubEvent) -> Self {
-    BehaviourEvent::FloodsubEvent(event)
+impl From<GossipsubEvent> for BehaviourEvent {
+  fn from(event: GossipsubEvent) -> Self {
+    BehaviourEvent::GossipsubEvent(event)
+  }
+}
+impl From<PingEvent> for BehaviourEvent {
+  fn from(event: PingEvent) -> Self {
+    BehaviourEvent::PingEvent(event)
   }
 }
\ No newline at end of file
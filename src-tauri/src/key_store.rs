@@ -0,0 +1,236 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use libp2p::identity;
+use log::{error, warn};
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Where the node key is persisted between runs
+pub trait KeyStore {
+    /// The stored key, `None` when none was stored yet
+    fn load(&self) -> Result<Option<identity::Keypair>>;
+    fn save(&self, keys: &identity::Keypair) -> Result<()>;
+    /// Where the key lives, for logs
+    fn location(&self) -> String;
+}
+
+fn encode(keys: &identity::Keypair) -> Result<Vec<u8>> {
+    match keys {
+        identity::Keypair::Ed25519(keys) => Ok(keys.encode().to_vec()),
+        _ => Err("only ed25519 node keys can be stored".into()),
+    }
+}
+
+fn decode(mut bytes: Vec<u8>) -> Result<identity::Keypair> {
+    let keys = identity::ed25519::Keypair::decode(&mut bytes)?;
+    Ok(identity::Keypair::Ed25519(keys))
+}
+
+/// Key stored as is in a file of the data directory
+pub struct FileKeyStore {
+    path: PathBuf,
+}
+
+impl FileKeyStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Where an undecodable key is moved, so generating a new one doesn't destroy it
+    fn corrupt_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".corrupt");
+        path.into()
+    }
+}
+
+impl KeyStore for FileKeyStore {
+    /// An undecodable file is moved aside and reported as no key
+    fn load(&self) -> Result<Option<identity::Keypair>> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match decode(bytes) {
+            Ok(keys) => Ok(Some(keys)),
+            Err(e) => {
+                error!("{} is corrupt, moving it aside: {}", self.location(), e);
+                fs::rename(&self.path, self.corrupt_path())?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Only readable by the current user on unix
+    fn save(&self, keys: &identity::Keypair) -> Result<()> {
+        let bytes = encode(keys)?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&self.path)?;
+        // the mode only applies to new files, older ones may be readable by anyone
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+        file.write_all(&bytes)?;
+        Ok(())
+    }
+
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+/// Moves the key of `file` into `to`, removing the file once the key is saved there. `None`
+/// when the file holds no key.
+pub fn import(file: &FileKeyStore, to: &dyn KeyStore) -> Result<Option<identity::Keypair>> {
+    let keys = match file.load()? {
+        Some(keys) => keys,
+        None => return Ok(None),
+    };
+    to.save(&keys)?;
+    if let Err(e) = fs::remove_file(&file.path) {
+        warn!("node key imported but {} is left: {}", file.location(), e);
+    }
+    Ok(Some(keys))
+}
+
+/// Key stored base58-encoded in the OS keychain, out of reach of other users of the machine
+pub struct KeychainKeyStore {
+    service: String,
+    user: String,
+}
+
+impl KeychainKeyStore {
+    pub fn new(service: &str, user: &str) -> Self {
+        Self {
+            service: service.to_owned(),
+            user: user.to_owned(),
+        }
+    }
+
+    fn entry(&self) -> keyring::Entry {
+        keyring::Entry::new(&self.service, &self.user)
+    }
+}
+
+impl KeyStore for KeychainKeyStore {
+    fn load(&self) -> Result<Option<identity::Keypair>> {
+        match self.entry().get_password() {
+            Ok(encoded) => decode(bs58::decode(encoded).into_vec()?).map(Some),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.to_string().into()),
+        }
+    }
+
+    fn save(&self, keys: &identity::Keypair) -> Result<()> {
+        let encoded = bs58::encode(encode(keys)?).into_string();
+        self.entry()
+            .set_password(&encoded)
+            .map_err(|e| e.to_string().into())
+    }
+
+    fn location(&self) -> String {
+        format!("keychain entry {}/{}", self.service, self.user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Stands in for the keychain
+    #[derive(Default)]
+    struct MemoryKeyStore {
+        keys: RefCell<Option<identity::Keypair>>,
+    }
+
+    impl KeyStore for MemoryKeyStore {
+        fn load(&self) -> Result<Option<identity::Keypair>> {
+            Ok(self.keys.borrow().clone())
+        }
+
+        fn save(&self, keys: &identity::Keypair) -> Result<()> {
+            *self.keys.borrow_mut() = Some(keys.clone());
+            Ok(())
+        }
+
+        fn location(&self) -> String {
+            "memory".to_owned()
+        }
+    }
+
+    #[test]
+    fn file_keys_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileKeyStore::new(dir.path().join("node_key"));
+        assert!(store.load().unwrap().is_none());
+
+        let keys = identity::Keypair::generate_ed25519();
+        store.save(&keys).unwrap();
+        assert_eq!(store.load().unwrap().unwrap().public(), keys.public());
+    }
+
+    #[test]
+    fn corrupt_key_files_are_moved_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node_key");
+        fs::write(&path, b"not a key").unwrap();
+        let store = FileKeyStore::new(&path);
+        assert!(store.load().unwrap().is_none());
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read(dir.path().join("node_key.corrupt")).unwrap(),
+            b"not a key"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saved_keys_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node_key");
+        fs::write(&path, b"older key").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let keys = identity::Keypair::generate_ed25519();
+        FileKeyStore::new(&path).save(&keys).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(
+            FileKeyStore::new(&path).load().unwrap().unwrap().public(),
+            keys.public()
+        );
+    }
+
+    #[test]
+    fn imported_keys_leave_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node_key");
+        let file = FileKeyStore::new(&path);
+        let keys = identity::Keypair::generate_ed25519();
+        file.save(&keys).unwrap();
+
+        let keychain = MemoryKeyStore::default();
+        let imported = import(&file, &keychain).unwrap().unwrap();
+        assert_eq!(imported.public(), keys.public());
+        assert_eq!(keychain.load().unwrap().unwrap().public(), keys.public());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn nothing_is_imported_without_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = FileKeyStore::new(dir.path().join("node_key"));
+        let keychain = MemoryKeyStore::default();
+        assert!(import(&file, &keychain).unwrap().is_none());
+        assert!(keychain.load().unwrap().is_none());
+    }
+}
@@ -3,30 +3,60 @@
     windows_subsystem = "windows"
 )]
 
+mod at_rest;
 mod behaviour;
+mod key_store;
+mod peer_store;
+mod presence;
+mod query;
+mod reconnect;
 
-use std::collections::HashSet;
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use serde_json::json;
 use tauri::{Manager, State, Window};
 
+use behaviour::MAX_MESSAGE_BYTES;
+use key_store::{FileKeyStore, KeyStore, KeychainKeyStore};
 use libp2p::{
-    core::upgrade,
-    floodsub::{Floodsub, FloodsubEvent, Topic},
-    futures::StreamExt,
+    core::{
+        muxing::StreamMuxerBox,
+        transport::{Boxed, MemoryTransport},
+        upgrade, ConnectedPoint,
+    },
+    futures::{AsyncRead, AsyncWrite, StreamExt},
+    gossipsub::{Gossipsub, GossipsubEvent, IdentTopic, TopicHash},
     identity,
     mdns::{Mdns, MdnsEvent},
     mplex,
-    noise::{Keypair, NoiseConfig, X25519Spec},
-    swarm::{NetworkBehaviourEventProcess, Swarm, SwarmBuilder},
+    multiaddr::Protocol,
+    multihash::Multihash,
+    noise::{AuthenticKeypair, Keypair, NoiseConfig, X25519Spec},
+    ping::{Ping, PingEvent, PingSuccess},
+    swarm::{toggle::Toggle, NetworkBehaviourEventProcess, Swarm, SwarmBuilder, SwarmEvent},
     tcp::TokioTcpConfig,
-    NetworkBehaviour, PeerId, Transport,
+    Multiaddr, NetworkBehaviour, PeerId, Transport, TransportError,
 };
-use log::{error, info};
-use once_cell::sync::Lazy;
+use log::{debug, error, info, warn};
+use once_cell::sync::{Lazy, OnceCell};
+use peer_store::PeerStore;
+use presence::{PeerInfo, Roster};
+use query::{Queries, QueryUpdate, DEFAULT_QUERY_TIMEOUT_SECS};
+use reconnect::Reconnects;
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{mpsc, oneshot, watch};
 
 #[derive(Clone, serde::Serialize)]
 struct Payload {
@@ -34,373 +64,6334 @@ struct Payload {
 }
 
 const STORAGE_FILE_NAME: &str = "votes.json";
+/// Overrides the full path of the votes file, handy to run several instances on one machine
+const STORAGE_PATH_ENV: &str = "VOTINGDAPP_STORAGE_PATH";
 
-fn get_storage_file_path() -> String {
-    format!(
-        "{}/{}",
-        tauri::api::path::data_dir().unwrap().display(),
-        STORAGE_FILE_NAME
-    )
+/// First directory `resolvers` come up with, in order, along with the name of its resolver
+fn first_dir<'a>(resolvers: &[(&'a str, fn() -> Option<PathBuf>)]) -> Option<(&'a str, PathBuf)> {
+    resolvers
+        .iter()
+        .find_map(|(name, resolve)| resolve().map(|dir| (*name, dir)))
+}
+
+/// Where data files go without `STORAGE_PATH_ENV`: the platform data directory, falling back
+/// to its config directory then to the working directory, along with which one it is. `None`
+/// when none is known.
+static DATA_DIR: Lazy<Option<(&str, PathBuf)>> = Lazy::new(|| {
+    let resolvers: [(&str, fn() -> Option<PathBuf>); 3] = [
+        ("data", tauri::api::path::data_dir),
+        ("config", tauri::api::path::config_dir),
+        ("working", || env::current_dir().ok()),
+    ];
+    first_dir(&resolvers)
+});
+
+fn data_dir() -> Option<&'static Path> {
+    DATA_DIR.as_ref().map(|(_, dir)| dir.as_path())
+}
+
+/// Says where data goes, once the logger is up to tell
+fn log_data_dir() {
+    if env::var_os(STORAGE_PATH_ENV).is_some() {
+        return;
+    }
+    match &*DATA_DIR {
+        Some((name, dir)) => info!("Storing data in the {} directory {}", name, dir.display()),
+        None => error!("No directory to store data in, set {}", STORAGE_PATH_ENV),
+    }
+}
+
+fn get_storage_file_path() -> std::result::Result<String, StorageError> {
+    let path = match env::var(STORAGE_PATH_ENV) {
+        Ok(path) => PathBuf::from(path),
+        Err(_e) => data_dir()
+            .ok_or(StorageError::NoDataDir)?
+            .join(peer_file_name(STORAGE_FILE_NAME)),
+    };
+    if let Some(parent) = path.parent() {
+        if let Err(e) = fs::create_dir_all(parent) {
+            error!(
+                "cannot create storage directory {}: {}",
+                parent.display(),
+                e
+            );
+        }
+    }
+    Ok(path.display().to_string())
+}
+
+const REMOTE_STORAGE_FILE_NAME: &str = "remote_votes.json";
+
+/// Keeps poll names usable in file names. Bytes other than ASCII letters, digits and `-` are
+/// written as `_` and two hex digits, so two polls never share a file.
+fn poll_file_stem(poll: &str) -> String {
+    let mut stem = String::with_capacity(poll.len());
+    for byte in poll.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' => stem.push(byte as char),
+            _ => stem.push_str(&format!("_{:02x}", byte)),
+        }
+    }
+    stem
+}
+
+/// The default poll keeps `votes.json`, others are stored in `votes_<poll>.json`
+fn poll_storage_file_path(poll: &str) -> std::result::Result<String, StorageError> {
+    if poll == TOPIC_NAME.as_str() {
+        get_storage_file_path()
+    } else {
+        get_data_file_path(&format!("votes_{}.json", poll_file_stem(poll)))
+    }
+}
+
+fn poll_remote_storage_file_path(poll: &str) -> std::result::Result<String, StorageError> {
+    if poll == TOPIC_NAME.as_str() {
+        get_data_file_path(REMOTE_STORAGE_FILE_NAME)
+    } else {
+        get_data_file_path(&format!("remote_votes_{}.json", poll_file_stem(poll)))
+    }
+}
+
+/// Other data files live next to the votes file
+fn get_data_file_path(file_name: &str) -> std::result::Result<String, StorageError> {
+    Ok(Path::new(&get_storage_file_path()?)
+        .with_file_name(peer_file_name(file_name))
+        .display()
+        .to_string())
+}
+
+/// Persisted ed25519 keypair, so the peer id survives restarts
+const NODE_KEY_FILE_NAME: &str = "node_key";
+
+/// Suffixes a data file name with the `--peer-name` flag, e.g. `votes.json` -> `votes_alice.json`
+fn peer_file_name(file_name: &str) -> String {
+    match (PEER_NAME.get(), file_name.rsplit_once('.')) {
+        (Some(peer), Some((stem, ext))) => format!("{}_{}.{}", stem, peer, ext),
+        (Some(peer), None) => format!("{}_{}", file_name, peer),
+        (None, _) => file_name.to_owned(),
+    }
+}
+
+fn parse_peer_name(mut args: impl Iterator<Item = String>) -> Option<String> {
+    while let Some(arg) = args.next() {
+        if arg == "--peer-name" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--peer-name=") {
+            return Some(name.to_owned());
+        }
+    }
+    None
 }
 
+/// Reads a setting from the environment, falling back to `default` when unset or invalid
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Name of the poll this node takes part in, peers only see votes from the same topic
+const TOPIC_ENV: &str = "VOTINGDAPP_TOPIC";
+const DEFAULT_TOPIC: &str = "votes";
+
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync + 'static>>;
 type Votes = Vec<Vote>;
+/// Public votes received from other peers, keyed by source peer id
+type RemoteVotes = HashMap<String, Votes>;
+
+/// Node key and the peer id derived from it
+struct Identity {
+    keys: identity::Keypair,
+    peer_id: PeerId,
+}
+
+impl Identity {
+    fn new(keys: identity::Keypair) -> Self {
+        Self {
+            peer_id: PeerId::from(keys.public()),
+            keys,
+        }
+    }
+}
+
+/// Loaded at first use, then only replaced by `regenerate_identity`
+static IDENTITY: Lazy<RwLock<Identity>> =
+    Lazy::new(|| RwLock::new(Identity::new(load_or_generate_keys())));
+
+fn node_keys() -> identity::Keypair {
+    IDENTITY
+        .read()
+        .expect("identity lock is poisoned")
+        .keys
+        .clone()
+}
+
+fn local_peer_id() -> PeerId {
+    IDENTITY.read().expect("identity lock is poisoned").peer_id
+}
+
+/// The default poll, joined at startup
+static TOPIC_NAME: Lazy<String> =
+    Lazy::new(|| env::var(TOPIC_ENV).unwrap_or_else(|_e| DEFAULT_TOPIC.to_owned()));
+static TOPIC: Lazy<IdentTopic> = Lazy::new(|| IdentTopic::new(TOPIC_NAME.as_str()));
+static PEER_NAME: OnceCell<String> = OnceCell::new();
+/// `<peer id>@<topic>` of this node, prefixed to every log line once the swarm is set up
+static LOG_CONTEXT: Lazy<RwLock<Option<String>>> = Lazy::new(|| RwLock::new(None));
+
+/// Set to `true` to keep the node key in the OS keychain rather than in the `node_key` file
+const USE_KEYCHAIN_ENV: &str = "VOTINGDAPP_USE_KEYCHAIN";
+const KEYCHAIN_SERVICE: &str = "votingdapp";
+
+fn node_key_store() -> Result<Box<dyn KeyStore>> {
+    if env_or(USE_KEYCHAIN_ENV, false) {
+        Ok(Box::new(KeychainKeyStore::new(
+            KEYCHAIN_SERVICE,
+            &peer_file_name(NODE_KEY_FILE_NAME),
+        )))
+    } else {
+        Ok(Box::new(FileKeyStore::new(get_data_file_path(
+            NODE_KEY_FILE_NAME,
+        )?)))
+    }
+}
+
+fn load_or_generate_keys() -> identity::Keypair {
+    let store = match node_key_store() {
+        Ok(store) => store,
+        Err(e) => {
+            error!("{}, using a temporary node key", e);
+            return identity::Keypair::generate_ed25519();
+        }
+    };
+    match store.load() {
+        Ok(Some(keys)) => return keys,
+        Ok(None) => {
+            if let Some(keys) = import_file_key(store.as_ref()) {
+                return keys;
+            }
+            info!("No node key in {}, generating one", store.location())
+        }
+        Err(e) => {
+            // saving a new key would replace the identity for good, while the stored one may be
+            // readable next time
+            error!(
+                "error loading node key from {}: {}, using a temporary one",
+                store.location(),
+                e
+            );
+            return identity::Keypair::generate_ed25519();
+        }
+    }
+    let keys = identity::Keypair::generate_ed25519();
+    if let Err(e) = store.save(&keys) {
+        error!("error saving node key to {}: {}", store.location(), e);
+    }
+    keys
+}
 
-static KEYS: Lazy<identity::Keypair> = Lazy::new(|| identity::Keypair::generate_ed25519());
-static PEER_ID: Lazy<PeerId> = Lazy::new(|| PeerId::from(KEYS.public()));
-static TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("votes"));
+/// Moves the `node_key` file of a run without the keychain into `store`, so switching to the
+/// keychain keeps the peer id. The file is used as is when the move fails.
+fn import_file_key(store: &dyn KeyStore) -> Option<identity::Keypair> {
+    if !env_or(USE_KEYCHAIN_ENV, false) {
+        return None;
+    }
+    let file = FileKeyStore::new(get_data_file_path(NODE_KEY_FILE_NAME).ok()?);
+    match key_store::import(&file, store) {
+        Ok(Some(keys)) => {
+            info!(
+                "Moved node key from {} to {}",
+                file.location(),
+                store.location()
+            );
+            Some(keys)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error!(
+                "error moving node key from {} to {}: {}",
+                file.location(),
+                store.location(),
+                e
+            );
+            file.load().ok().flatten()
+        }
+    }
+}
+
+/// Generates a node key and persists it in `store`, replacing the previous one
+fn regenerate_keys(store: &dyn KeyStore) -> Result<identity::Keypair> {
+    let keys = identity::Keypair::generate_ed25519();
+    store.save(&keys)?;
+    Ok(keys)
+}
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 struct Language {
-    name: &'static str,
-}
-
-const LANGUAGES: [Language; 21] = [
-    Language { name: "Elm" },
-    Language { name: "Rust" },
-    Language { name: "JavaScript" },
-    Language { name: "TypeScript" },
-    Language { name: "Elixir" },
-    Language { name: "Ruby" },
-    Language { name: "OCaml" },
-    Language { name: "Python" },
-    Language { name: "R" },
-    Language { name: "Go" },
-    Language { name: "CSharp" },
-    Language { name: "Haskell" },
-    Language { name: "Clojure" },
-    Language { name: "Java" },
-    Language { name: "Dart" },
-    Language { name: "Julia" },
-    Language { name: "Kotlin" },
-    Language { name: "Swift" },
-    Language { name: "Erlang" },
-    Language { name: "Lua" },
-    Language { name: "PHP" },
+    name: String,
+}
+
+const BUILTIN_LANGUAGES: [&str; 21] = [
+    "Elm",
+    "Rust",
+    "JavaScript",
+    "TypeScript",
+    "Elixir",
+    "Ruby",
+    "OCaml",
+    "Python",
+    "R",
+    "Go",
+    "CSharp",
+    "Haskell",
+    "Clojure",
+    "Java",
+    "Dart",
+    "Julia",
+    "Kotlin",
+    "Swift",
+    "Erlang",
+    "Lua",
+    "PHP",
 ];
 
+/// Per-peer file customizing the languages that can be voted for
+const LANGUAGES_FILE_NAME: &str = "languages.json";
+
+/// Content of `languages.json`, its languages extend the built-in ones unless `replace` is set
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LanguageOverrides {
+    #[serde(default)]
+    replace: bool,
+    languages: Vec<String>,
+}
+
+fn read_language_overrides() -> std::result::Result<LanguageOverrides, StorageError> {
+    match fs::read(get_data_file_path(LANGUAGES_FILE_NAME)?) {
+        Ok(content) => Ok(serde_json::from_slice(&content)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(LanguageOverrides::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_language_overrides(overrides: &LanguageOverrides) -> Result<()> {
+    let json = serde_json::to_string_pretty(overrides)?;
+    fs::write(get_data_file_path(LANGUAGES_FILE_NAME)?, &json)?;
+    Ok(())
+}
+
+/// Languages that can be voted for, built-in ones first
+fn known_languages() -> std::result::Result<Vec<Language>, StorageError> {
+    let overrides = read_language_overrides()?;
+    let mut names: Vec<String> = vec![];
+    if !overrides.replace {
+        names.extend(BUILTIN_LANGUAGES.iter().map(|&name| name.to_owned()));
+    }
+    for name in overrides.languages {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    Ok(names.into_iter().map(|name| Language { name }).collect())
+}
+
+fn add_custom_language(name: &str) -> Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("language name is empty".into());
+    }
+    if is_valid_language(name)? {
+        return Err(format!("{} is already a language", name).into());
+    }
+    let mut overrides = read_language_overrides()?;
+    overrides.languages.push(name.to_owned());
+    write_language_overrides(&overrides)
+}
+
+/// Removing a built-in language switches the file to `replace` mode, listing all the other ones
+fn remove_custom_language(name: &str) -> Result<()> {
+    let languages = known_languages()?;
+    if !languages.iter().any(|l| l.name == name) {
+        return Err(format!("unknown language {}", name).into());
+    }
+    let mut overrides = read_language_overrides()?;
+    if BUILTIN_LANGUAGES.contains(&name) && !overrides.replace {
+        overrides.replace = true;
+        overrides.languages = languages.into_iter().map(|l| l.name).collect();
+    }
+    overrides.languages.retain(|l| l != name);
+    write_language_overrides(&overrides)
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct Vote {
     id: usize,
     name: String,
     public: bool,
+    /// Unix time in milliseconds, 0 for votes stored before it was recorded
+    #[serde(default)]
+    created_at: u64,
+    /// Set by the sending node on votes it shares, empty for votes kept locally. A byte string
+    /// in CBOR rather than an array of integers, JSON still writes it as an array of numbers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "serde_bytes")]
+    signature: Vec<u8>,
+    /// Why the voter picked this language, absent from older votes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    /// Preference strength, from `MIN_WEIGHT` to `MAX_WEIGHT`. Left out when it's the default,
+    /// so the signed payload of unweighted votes is the same as before the field existed.
+    #[serde(default = "default_weight", skip_serializing_if = "is_default_weight")]
+    weight: u8,
+    /// Unix time in milliseconds after which the vote is deleted, never when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+const MIN_WEIGHT: u8 = 1;
+const MAX_WEIGHT: u8 = 5;
+
+fn default_weight() -> u8 {
+    MIN_WEIGHT
+}
+
+fn is_default_weight(weight: &u8) -> bool {
+    *weight == MIN_WEIGHT
+}
+
+/// Multihash code used by peer ids that inline their public key, as ed25519 ones do
+const IDENTITY_MULTIHASH_CODE: u64 = 0x00;
+
+/// The signed bytes are the vote serialized without its signature
+fn vote_payload(vote: &Vote) -> Result<Vec<u8>> {
+    let unsigned = Vote {
+        signature: vec![],
+        ..vote.clone()
+    };
+    Ok(serde_json::to_vec(&unsigned)?)
+}
+
+fn sign_vote(keys: &identity::Keypair, mut vote: Vote) -> Result<Vote> {
+    vote.signature = keys.sign(&vote_payload(&vote)?)?;
+    Ok(vote)
+}
+
+fn public_key_of(peer_id: &PeerId) -> Option<identity::PublicKey> {
+    let multihash = Multihash::from(*peer_id);
+    if multihash.code() != IDENTITY_MULTIHASH_CODE {
+        return None;
+    }
+    identity::PublicKey::from_protobuf_encoding(multihash.digest()).ok()
+}
+
+fn verify_vote(vote: &Vote, source: &PeerId) -> bool {
+    match (public_key_of(source), vote_payload(vote)) {
+        (Some(key), Ok(payload)) => key.verify(&payload, &vote.signature),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 enum ListMode {
     ALL,
     One(String),
+    /// Votes of the given poll, whatever topic the request went through. Peers predating it
+    /// can't parse the request and ignore it.
+    Topic(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ListRequest {
     mode: ListMode,
+    /// Echoed in responses so they can be matched to the query, absent from older peers
+    #[serde(default)]
+    request_id: Option<String>,
+    /// Times the request was sent again for lack of answers. A retry isn't the same bytes as the
+    /// first attempt that way, which gossipsub and peers would drop as a duplicate.
+    #[serde(default, skip_serializing_if = "is_first_attempt")]
+    attempt: u8,
 }
 
+fn is_first_attempt(attempt: &u8) -> bool {
+    *attempt == 0
+}
+
+/// `receiver` of responses meant for every peer, like the announce sent when voting
+const BROADCAST_RECEIVER: &str = "any";
+
 #[derive(Debug, Serialize, Deserialize)]
 struct ListResponse {
     mode: ListMode,
     data: Votes,
+    /// Peer id the response answers, or `BROADCAST_RECEIVER`
     receiver: String,
+    #[serde(default)]
+    request_id: Option<String>,
+    /// Random value making a `rebroadcast` differ from the previous one, which gossipsub would
+    /// otherwise drop as a duplicate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nonce: Option<u64>,
 }
 
-enum EventType {
-    Response(ListResponse),
+/// Heartbeat published on the default topic so peers know who is online
+#[derive(Debug, Serialize, Deserialize)]
+struct Presence {
+    peer_id: String,
+    /// Unix timestamp, in milliseconds, as told by the sender's clock
+    timestamp: u64,
+    /// Human name shown instead of the peer id, see `DISPLAY_NAME_ENV`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    display_name: Option<String>,
 }
 
-#[derive(NetworkBehaviour)]
-struct VoteBehaviour {
-    floodsub: Floodsub,
-    mdns: Mdns,
-    #[behaviour(ignore)]
-    response_sender: mpsc::UnboundedSender<ListResponse>,
-}
+/// Name peers show for this node, defaults to the peer name given on the command line
+const DISPLAY_NAME_ENV: &str = "VOTINGDAPP_DISPLAY_NAME";
 
-struct SenderState {
-    sender: mpsc::UnboundedSender<ListResponse>,
-}
+static DISPLAY_NAME: Lazy<Option<String>> = Lazy::new(|| {
+    env::var(DISPLAY_NAME_ENV)
+        .ok()
+        .or_else(|| PEER_NAME.get().cloned())
+        .and_then(|name| presence::sanitize_label(&name))
+});
 
-impl NetworkBehaviourEventProcess<FloodsubEvent> for VoteBehaviour {
-    fn inject_event(&mut self, event: FloodsubEvent) {
-        match event {
-            FloodsubEvent::Message(msg) => {
-                if let Ok(resp) = serde_json::from_slice::<ListResponse>(&msg.data) {
-                    if resp.receiver == PEER_ID.to_string() {
-                        info!("Response from {}:", msg.source);
-                        resp.data.iter().for_each(|r| info!("{:?}", r));
-                    }
-                } else if let Ok(req) = serde_json::from_slice::<ListRequest>(&msg.data) {
-                    match req.mode {
-                        ListMode::ALL => {
-                            info!("Received ALL req: {:?} from {:?}", req, msg.source);
-                            respond_with_public_votes(
-                                self.response_sender.clone(),
-                                msg.source.to_string(),
-                            );
-                        }
-                        ListMode::One(ref peer_id) => {
-                            if peer_id == &PEER_ID.to_string() {
-                                info!("Received req: {:?} from {:?}", req, msg.source);
-                                respond_with_public_votes(
-                                    self.response_sender.clone(),
-                                    msg.source.to_string(),
-                                );
-                            }
-                        }
-                    }
-                }
-            }
-            _ => (),
-        }
-    }
-}
+/// How often a `Presence` heartbeat is published, in seconds
+const PRESENCE_INTERVAL_ENV: &str = "VOTINGDAPP_PRESENCE_INTERVAL_SECS";
+const DEFAULT_PRESENCE_INTERVAL_SECS: u64 = 10;
 
-fn respond_with_public_votes(sender: mpsc::UnboundedSender<ListResponse>, receiver: String) {
-    tokio::spawn(async move {
-        match read_local_votes() {
-            Ok(votes) => {
-                let resp = ListResponse {
-                    mode: ListMode::ALL,
-                    receiver,
-                    data: votes.into_iter().filter(|r| r.public).collect(),
-                };
-                if let Err(e) = sender.send(resp) {
-                    error!("error sending response via channel, {}", e);
-                }
-            }
-            Err(e) => error!("error fetching local votes to answer ALL request, {}", e),
-        }
-    });
+fn presence_interval() -> Duration {
+    Duration::from_secs(env_or(PRESENCE_INTERVAL_ENV, DEFAULT_PRESENCE_INTERVAL_SECS).max(1))
 }
 
-impl NetworkBehaviourEventProcess<MdnsEvent> for VoteBehaviour {
-    fn inject_event(&mut self, event: MdnsEvent) {
-        match event {
-            MdnsEvent::Discovered(discovered_list) => {
-                for (peer, _addr) in discovered_list {
-                    self.floodsub.add_node_to_partial_view(peer);
-                }
-            }
-            MdnsEvent::Expired(expired_list) => {
-                for (peer, _addr) in expired_list {
-                    if !self.mdns.has_node(&peer) {
-                        self.floodsub.remove_node_from_partial_view(&peer);
-                    }
-                }
-            }
-        }
-    }
-}
+static NEXT_REQUEST: AtomicU64 = AtomicU64::new(0);
 
-fn add_vote(name: &str) -> Result<Vote> {
-    let mut local_votes = read_local_votes()?;
-    info!("{:?}", local_votes);
-    let new_id = match local_votes.iter().max_by_key(|r| r.id) {
-        Some(v) => v.id + 1,
-        None => 0,
-    };
-    let vote = Vote {
-        id: new_id,
-        name: name.to_owned(),
-        public: false,
-    };
-    local_votes.push(vote.clone());
-    write_local_votes(&local_votes)?;
+fn next_request_id() -> String {
+    format!(
+        "{}-{}",
+        now_millis(),
+        NEXT_REQUEST.fetch_add(1, Ordering::Relaxed)
+    )
+}
 
-    info!("Added vote:");
-    info!("Name: {}", name);
+/// How long to wait for peers to answer a query, in seconds
+const QUERY_TIMEOUT_ENV: &str = "VOTINGDAPP_QUERY_TIMEOUT_SECS";
+/// How often outstanding queries are checked for timeouts, and bootstrap redials for being due
+const QUERY_TICK: Duration = Duration::from_millis(250);
+/// How often expired votes are looked for
+const EXPIRY_TICK: Duration = Duration::from_secs(60);
 
-    Ok(vote)
+/// Where a response should be delivered
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ResponseTarget {
+    /// Everyone subscribed to the topic
+    Topic,
+    /// Only the peer that asked
+    Peer(PeerId),
 }
 
-async fn publish_vote(id: usize) -> Result<()> {
-    let mut local_votes = read_local_votes()?;
-    local_votes
-        .iter_mut()
-        .filter(|r| r.id == id)
-        .for_each(|r| r.public = true);
-    write_local_votes(&local_votes)?;
-    Ok(())
+/// A response on its way out of the node
+struct OutgoingResponse {
+    /// Topic of the poll the response is about
+    poll: String,
+    response: ListResponse,
+    target: ResponseTarget,
 }
 
-fn read_local_votes() -> Result<Votes> {
-    match fs::read(get_storage_file_path()) {
-        Ok(votes) => Ok(serde_json::from_slice(&votes)?),
-        Err(_e) => Ok(vec![]),
-    }
+enum EventType {
+    Response(OutgoingResponse),
+    Command(SwarmCommand),
+    QueryTick,
+    Heartbeat,
+    ExpiryTick,
 }
 
-fn write_local_votes(votes: &Votes) -> Result<()> {
-    let json = serde_json::to_string(&votes)?;
+/// Actions requested by commands on the swarm owned by the `initialize` loop
+enum SwarmCommand {
+    /// Starts dialing `address`, answering whether the dial could be started
+    Dial {
+        address: Multiaddr,
+        reply: oneshot::Sender<std::result::Result<(), String>>,
+    },
+    JoinPoll {
+        topic: String,
+        reply: oneshot::Sender<std::result::Result<(), String>>,
+    },
+    LeavePoll {
+        topic: String,
+        reply: oneshot::Sender<std::result::Result<(), String>>,
+    },
+    /// Subscribes to the default topic again (`true`) or leaves it (`false`)
+    SetOnline {
+        online: bool,
+        reply: oneshot::Sender<std::result::Result<(), String>>,
+    },
+    Cli(Command),
+    /// Asks `peer_id` for its public votes on `poll`, answering through `reply` once the query completes
+    FetchPeerVotes {
+        peer_id: PeerId,
+        poll: String,
+        reply: oneshot::Sender<std::result::Result<Votes, String>>,
+    },
+    /// Stops waiting for a query, answering whether it was still outstanding
+    CancelQuery {
+        request_id: String,
+        reply: oneshot::Sender<bool>,
+    },
+    /// Lists the peers gossipsub knows to be subscribed to `poll`
+    SubscribedPeers {
+        poll: String,
+        reply: oneshot::Sender<Vec<String>>,
+    },
+    /// Publishes every public vote of `poll`, answering how many were sent
+    Rebroadcast {
+        poll: String,
+        reply: oneshot::Sender<std::result::Result<usize, String>>,
+    },
+    /// Replaces the node key and restarts the swarm with it, answering the new peer id
+    RegenerateIdentity {
+        reply: oneshot::Sender<std::result::Result<String, String>>,
+    },
+}
 
-    fs::write(get_storage_file_path(), &json)?;
-    Ok(())
+/// A `fetch_peer_votes` call waiting for its query to complete
+struct PendingFetch {
+    reply: oneshot::Sender<std::result::Result<Votes, String>>,
+    votes: Votes,
 }
 
-async fn handle_list_peers(swarm: &mut Swarm<VoteBehaviour>) {
-    info!("Discovered Peers:");
-    let nodes = swarm.behaviour().mdns.discovered_nodes();
-    let mut unique_peers = HashSet::new();
-    for peer in nodes {
-        unique_peers.insert(peer);
+impl PendingFetch {
+    /// Answers the caller once its query completed, with an error when nobody answered
+    fn complete(self, request_id: &str, peers_responded: usize) {
+        let result = match peers_responded {
+            0 => Err(format!("no answer to query {}", request_id)),
+            _ => Ok(self.votes),
+        };
+        // the caller may have gone away, nothing to do then
+        let _ = self.reply.send(result);
     }
-    unique_peers.iter().for_each(|p| info!("{}", p));
 }
 
-async fn handle_list_recipes(cmd: &str, swarm: &mut Swarm<VoteBehaviour>) {
-    let rest = cmd.strip_prefix("ls r ");
-    match rest {
-        Some("all") => {
-            let req = ListRequest {
-                mode: ListMode::ALL,
-            };
-            let json = serde_json::to_string(&req).expect("cannot jsonify request");
-            swarm
-                .behaviour_mut()
-                .floodsub
-                .publish(TOPIC.clone(), json.as_bytes());
-        }
-        Some(recipes_peer_id) => {
-            let req = ListRequest {
-                mode: ListMode::One(recipes_peer_id.to_owned()),
-            };
-            let json = serde_json::to_string(&req).expect("cannot jsonify request");
-            swarm
-                .behaviour_mut()
-                .floodsub
-                .publish(TOPIC.clone(), json.as_bytes());
-        }
-        None => {
-            match read_local_votes() {
-                Ok(v) => {
-                    info!("Local Recipes ({})", v.len());
-                    v.iter().for_each(|r| info!("{:?}", r));
-                }
-                Err(e) => error!("error fetching local votes: {}", e),
-            };
-        }
-    };
-}
+/// Fixed port to listen on, TCP or `/memory/` one, a random one is picked when unset
+const PORT_ENV: &str = "VOTINGDAPP_PORT";
 
-#[tauri::command]
-fn on_publish_vote(name: String, window: Window, state: State<SenderState>) -> tauri::Result<()> {
-    add_vote(name.as_str()).expect("Could not write");
+/// Set to `true` to only listen on loopback and disable mDNS, so the node is invisible on the LAN.
+/// Only peers on the same machine dialing us explicitly, or dialed through `VOTINGDAPP_BOOTSTRAP`,
+/// can then connect.
+const LOCAL_ONLY_ENV: &str = "VOTINGDAPP_LOCAL_ONLY";
 
-    let cloned_state = state.sender.clone();
+static LOCAL_ONLY: Lazy<bool> = Lazy::new(|| env_or(LOCAL_ONLY_ENV, false));
 
-    tauri::async_runtime::spawn(async move {
-        respond_with_public_votes(cloned_state, String::from("any"));
-    });
+/// Set to `true` to receive messages as usual but never publish any, logging what would have
+/// been sent instead. Lets a user watch the traffic without taking part.
+const DRY_RUN_ENV: &str = "VOTINGDAPP_DRY_RUN";
 
-    window
-        .emit(
-            "get_votes",
-            json!({
-                "votes": read_local_votes().unwrap(),
-            }),
-        )
-        .expect("failed to emit get_votes event");
+static DRY_RUN: Lazy<bool> = Lazy::new(|| env_or(DRY_RUN_ENV, false));
 
-    Ok(())
-}
+/// Set to `true` for a spectator node, like a projector showing results: votes can't be added
+/// or changed, and requests from peers are left unanswered.
+const OBSERVER_ENV: &str = "VOTINGDAPP_OBSERVER";
 
-async fn initialize(window: &Window) {
-    info!("Peer Id: {}", PEER_ID.clone());
-    let (response_sender, mut response_rcv) = mpsc::unbounded_channel();
+static OBSERVER: Lazy<bool> = Lazy::new(|| env_or(OBSERVER_ENV, false));
 
-    window.manage(SenderState {
-        sender: response_sender,
-    });
+/// Whether peers asking for our votes, or subscribing, are sent them: never while offline nor
+/// in observer mode
+fn answers_peers(offline: bool, observer: bool) -> bool {
+    !offline && !observer
+}
 
-    let auth_keys = Keypair::<X25519Spec>::new()
-        .into_authentic(&KEYS)
-        .expect("can't create auth keys");
+/// Refuses changes to votes in observer mode
+fn check_can_change_votes(observer: bool) -> std::result::Result<(), &'static str> {
+    match observer {
+        true => Err("this node is an observer, votes can't be changed"),
+        false => Ok(()),
+    }
+}
 
-    let transp = TokioTcpConfig::new()
-        .upgrade(upgrade::Version::V1)
-        .authenticate(NoiseConfig::xx(auth_keys).into_authenticated()) // XX Handshake pattern, IX exists as well and IK - only XX currently provides interop with other libp2p impls
-        .multiplex(mplex::MplexConfig::new())
-        .boxed();
+/// `tcp` (default) or `memory`, the latter only reaching nodes running in the same process
+const TRANSPORT_ENV: &str = "VOTINGDAPP_TRANSPORT";
 
-    let mut behaviour = behaviour::Behaviour::new(PEER_ID.clone()).await;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TransportKind {
+    Tcp,
+    Memory,
+}
 
-    behaviour.floodsub.subscribe(TOPIC.clone());
+impl FromStr for TransportKind {
+    type Err = String;
 
-    let mut swarm = SwarmBuilder::new(transp, behaviour, PEER_ID.clone())
-        .executor(Box::new(|fut| {
-            tokio::spawn(fut);
-        }))
-        .build();
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "tcp" => Ok(TransportKind::Tcp),
+            "memory" => Ok(TransportKind::Memory),
+            _ => Err(format!("unknown transport: {}", s)),
+        }
+    }
+}
 
-    Swarm::listen_on(
-        &mut swarm,
-        "/ip4/0.0.0.0/tcp/0"
-            .parse()
-            .expect("can't get a local socket"),
-    )
-    .expect("swarm can't be started");
+/// IP versions listened on over TCP: `v4`, `v6` or `dual` (default)
+const IP_FAMILIES_ENV: &str = "VOTINGDAPP_IP_FAMILIES";
 
-    loop {
-        let evt = {
-            tokio::select! {
-                response = response_rcv.recv() => Some(EventType::Response(response.expect("response doesn't exist"))),
-                event = swarm.select_next_some() => {
-                    info!("Unhandled Swarm Event: {:?}", event);
-                    None
-                },
-            }
-        };
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum IpFamilies {
+    V4,
+    V6,
+    Dual,
+}
 
-        if let Some(event) = evt {
-            match event {
-                EventType::Response(resp) => {
-                    let json = serde_json::to_string(&resp).expect("cannot jsonify response");
-                    println!("Received data {:?}", json);
-                    window.emit("new", &json).unwrap();
-                }
-            }
+impl FromStr for IpFamilies {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "v4" => Ok(IpFamilies::V4),
+            "v6" => Ok(IpFamilies::V6),
+            "dual" => Ok(IpFamilies::Dual),
+            _ => Err(format!("unknown address families: {}", s)),
         }
     }
 }
 
-fn main() {
-    pretty_env_logger::init();
+/// Comma-separated multiaddrs dialed at startup, to reach peers outside the LAN
+const BOOTSTRAP_ENV: &str = "VOTINGDAPP_BOOTSTRAP";
 
-    tauri::Builder::default()
-        .setup(|app| {
-            #[cfg(debug_assertions)]
-            app.get_window("main").unwrap().open_devtools();
+/// How long handled messages are remembered, in seconds
+const DEDUP_WINDOW_ENV: &str = "VOTINGDAPP_DEDUP_WINDOW_SECS";
+const DEFAULT_DEDUP_WINDOW_SECS: u64 = 60;
 
-            let window = app.get_window("main").unwrap();
+/// Keys seen within a sliding time window, older ones being forgotten
+struct RecentKeys<K> {
+    window: Duration,
+    seen: HashMap<K, Instant>,
+}
 
-            let wintwo = app.get_window("main").unwrap();
+impl<K: Hash + Eq> RecentKeys<K> {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: HashMap::new(),
+        }
+    }
 
-            app.get_window("main").unwrap().listen("ping", move |_| {
-                wintwo
-                    .emit(
-                        "get_languages",
-                        json!({
-                            "languages": LANGUAGES,
-                        }),
-                    )
-                    .expect("failed to emit get_votes event");
-                wintwo
-                    .emit(
-                        "get_votes",
-                        json!({
-                            "votes": read_local_votes().unwrap(),
-                        }),
-                    )
-                    .expect("failed to emit get_votes event");
-            });
+    /// Returns `false` when `key` was already seen within the window
+    fn insert(&mut self, key: K) -> bool {
+        let now = Instant::now();
+        let window = self.window;
+        self.seen
+            .retain(|_, seen_at| now.duration_since(*seen_at) < window);
 
-            tauri::async_runtime::spawn(async move {
-                initialize(&window).await;
+        match self.seen.entry(key) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(now);
+                true
+            }
+        }
+    }
+}
+
+/// Identifies a message by its author as well as its content, two peers sending the same bytes
+/// (e.g. both answering a query with no votes) being two different answers
+fn message_hash(source: &PeerId, data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// What becomes of a gossipsub payload
+enum Received {
+    /// Over `MAX_MESSAGE_BYTES`, so a peer can't make every node parse arbitrarily large payloads
+    Oversized,
+    /// Already handled within the dedup window
+    Duplicate,
+    /// `None` when the payload isn't a known message
+    Parsed(Option<IncomingMessage>),
+}
+
+/// Checks the size of a payload from `source`, then whether it was seen already, and only then
+/// parses it
+fn receive(seen: &mut RecentKeys<u64>, source: &PeerId, data: &[u8]) -> Received {
+    if data.len() > MAX_MESSAGE_BYTES {
+        return Received::Oversized;
+    }
+    if !seen.insert(message_hash(source, data)) {
+        return Received::Duplicate;
+    }
+    Received::Parsed(classify_message(data))
+}
+
+/// Minimum delay between two answers to `ALL` requests from the same peer
+const ALL_RESPONSE_INTERVAL: Duration = Duration::from_secs(5);
+/// Minimum delay between two answers to the same peer subscribing to a poll
+const SUBSCRIBE_RESPONSE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Who gets our votes in answer to a request or a subscription, and how
+#[derive(Debug, PartialEq)]
+struct Answer {
+    receiver: String,
+    mode: ListMode,
+    target: ResponseTarget,
+}
+
+/// Decides which requests and subscriptions are answered, rate-limiting each peer
+struct Answers {
+    /// Peers recently answered, so a spammed `ALL` request can't be used for amplification
+    requests: RecentKeys<PeerId>,
+    /// Peers recently sent our votes on subscribing, kept apart so their startup query still
+    /// gets an answer carrying its request id. Toggling a subscription can't make the whole
+    /// mesh flood the topic.
+    subscriptions: RecentKeys<PeerId>,
+}
+
+impl Answers {
+    fn new() -> Self {
+        Self {
+            requests: RecentKeys::new(ALL_RESPONSE_INTERVAL),
+            subscriptions: RecentKeys::new(SUBSCRIBE_RESPONSE_INTERVAL),
+        }
+    }
+
+    /// How to answer `req` from `source`, `None` when it's left unanswered. `local` is our
+    /// peer id, the only one `One` requests are answered for.
+    fn request(&mut self, source: PeerId, req: &ListRequest, local: &PeerId) -> Option<Answer> {
+        let target = match &req.mode {
+            ListMode::ALL => ResponseTarget::Topic,
+            ListMode::One(peer_id) => {
+                return match parse_peer_id(peer_id) {
+                    Ok(peer_id) if peer_id == *local => Some(Answer {
+                        receiver: source.to_string(),
+                        mode: ListMode::One(peer_id.to_string()),
+                        target: ResponseTarget::Peer(source),
+                    }),
+                    _ => None,
+                };
+            }
+            ListMode::Topic(_) => ResponseTarget::Peer(source),
+        };
+        self.requests.insert(source).then(|| Answer {
+            receiver: source.to_string(),
+            mode: req.mode.clone(),
+            target,
+        })
+    }
+
+    /// How to send our votes to `peer` that just subscribed to a poll, `None` when it was sent
+    /// them recently
+    fn subscription(&mut self, peer: PeerId) -> Option<Answer> {
+        self.subscriptions.insert(peer).then(|| Answer {
+            receiver: peer.to_string(),
+            mode: ListMode::ALL,
+            target: ResponseTarget::Peer(peer),
+        })
+    }
+}
+
+/// Addresses mDNS currently knows for each peer, a peer is only forgotten once all of them expired
+#[derive(Default)]
+struct PeerAddrs {
+    addrs: HashMap<PeerId, HashSet<Multiaddr>>,
+}
+
+impl PeerAddrs {
+    /// Records `addr` of `peer`, returning `true` when the peer wasn't known under any address
+    fn discovered(&mut self, peer: PeerId, addr: Multiaddr) -> bool {
+        let addrs = self.addrs.entry(peer).or_default();
+        let new_peer = addrs.is_empty();
+        addrs.insert(addr);
+        new_peer
+    }
+
+    /// Forgets `addr` of `peer`, returning `true` when it was the last one left
+    fn expired(&mut self, peer: PeerId, addr: &Multiaddr) -> bool {
+        match self.addrs.entry(peer) {
+            Entry::Occupied(mut addrs) => {
+                addrs.get_mut().remove(addr);
+                let gone = addrs.get().is_empty();
+                if gone {
+                    addrs.remove();
+                }
+                gone
+            }
+            Entry::Vacant(_) => false,
+        }
+    }
+
+    fn forget(&mut self, peer: &PeerId) {
+        self.addrs.remove(peer);
+    }
+}
+
+#[derive(NetworkBehaviour)]
+struct VoteBehaviour {
+    gossipsub: Gossipsub,
+    mdns: Toggle<Mdns>,
+    ping: Ping,
+    #[behaviour(ignore)]
+    response_sender: mpsc::UnboundedSender<OutgoingResponse>,
+    #[behaviour(ignore)]
+    window: Window,
+    #[behaviour(ignore)]
+    peers: Arc<Mutex<HashSet<PeerId>>>,
+    /// Hashes of recently handled messages, so the same payload delivered twice by the same
+    /// author is only processed once
+    #[behaviour(ignore)]
+    seen_messages: RecentKeys<u64>,
+    #[behaviour(ignore)]
+    answers: Answers,
+    #[behaviour(ignore)]
+    queries: Queries<ListRequest>,
+    #[behaviour(ignore)]
+    peer_addrs: PeerAddrs,
+    #[behaviour(ignore)]
+    latencies: Arc<Mutex<HashMap<PeerId, Duration>>>,
+    /// Consecutive ping failures per peer, reset by any successful ping
+    #[behaviour(ignore)]
+    ping_failures: HashMap<PeerId, u32>,
+    /// Votes received so far for each `fetch_peer_votes` query, keyed by request id
+    #[behaviour(ignore)]
+    fetches: HashMap<String, PendingFetch>,
+    #[behaviour(ignore)]
+    counters: Arc<Counters>,
+    #[behaviour(ignore)]
+    sync: Option<InitialSync>,
+    #[behaviour(ignore)]
+    roster: Arc<Mutex<Roster>>,
+    /// Set by `go_offline`: requests are left unanswered and no heartbeat is sent
+    #[behaviour(ignore)]
+    offline: bool,
+}
+
+/// Progress of the `ALL` query sent at startup, so the frontend can show the votes loading
+struct InitialSync {
+    request_id: String,
+    peers: HashSet<PeerId>,
+    votes_merged: usize,
+}
+
+impl InitialSync {
+    fn new(request_id: String) -> Self {
+        Self {
+            request_id,
+            peers: HashSet::new(),
+            votes_merged: 0,
+        }
+    }
+
+    fn record(&mut self, peer: PeerId, votes_merged: usize) {
+        self.peers.insert(peer);
+        self.votes_merged += votes_merged;
+    }
+
+    fn progress(&self) -> serde_json::Value {
+        json!({
+            "peers_responded": self.peers.len(),
+            "votes_merged": self.votes_merged,
+        })
+    }
+
+    /// The sync in progress when `request_id` is its query, `None` for any other response
+    fn answered_by<'a>(
+        sync: &'a mut Option<Self>,
+        request_id: Option<&str>,
+    ) -> Option<&'a mut Self> {
+        sync.as_mut()
+            .filter(|in_progress| request_id == Some(in_progress.request_id.as_str()))
+    }
+
+    /// Ends the sync in progress when `request_id`, its query, completed or was cancelled
+    fn finish(sync: &mut Option<Self>, request_id: &str) -> Option<Self> {
+        let ended = sync
+            .as_ref()
+            .map_or(false, |in_progress| in_progress.request_id == request_id);
+        match ended {
+            true => sync.take(),
+            false => None,
+        }
+    }
+}
+
+impl VoteBehaviour {
+    fn emit_peers_changed(&self) {
+        if let Err(e) = self.window.emit(
+            "peers_changed",
+            json!({ "peers": list_known_peers(&self.peers) }),
+        ) {
+            error!("error emitting peers_changed event, {}", e);
+        }
+    }
+}
+
+struct SenderState {
+    sender: mpsc::UnboundedSender<OutgoingResponse>,
+}
+
+struct SwarmState {
+    commands: mpsc::UnboundedSender<SwarmCommand>,
+}
+
+impl SwarmState {
+    /// Sends the command built around a reply channel to the `initialize` loop, and waits for
+    /// the loop to answer. The swarm never leaves the loop, so nothing is locked meanwhile.
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> SwarmCommand,
+    ) -> std::result::Result<T, String> {
+        let (reply, answer) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .map_err(|e| e.to_string())?;
+        answer.await.map_err(|e| e.to_string())
+    }
+}
+
+/// Peers discovered through mDNS, shared between the swarm and the commands
+#[derive(Default)]
+struct PeersState {
+    peers: Arc<Mutex<HashSet<PeerId>>>,
+}
+
+/// Addresses the swarm is listening on, as reported by `SwarmEvent::NewListenAddr`
+#[derive(Default)]
+struct ListenAddrsState {
+    addrs: Arc<Mutex<Vec<Multiaddr>>>,
+}
+
+/// When `rebroadcast` last published, to rate-limit it
+#[derive(Default)]
+struct RebroadcastState {
+    last: Mutex<Option<Instant>>,
+}
+
+/// Shortest delay between two `rebroadcast` calls
+const REBROADCAST_INTERVAL: Duration = Duration::from_secs(30);
+
+impl RebroadcastState {
+    /// Takes the slot of a rebroadcast starting at `now`, so concurrent calls are turned down
+    /// while it is published. Returns the previous start to `release` to, or how long ago it was
+    /// when that is too recent.
+    fn reserve(&self, now: Instant) -> std::result::Result<Option<Instant>, Duration> {
+        let mut last = self.last.lock().expect("rebroadcast lock is poisoned");
+        match last.map(|last| now.saturating_duration_since(last)) {
+            Some(elapsed) if elapsed < REBROADCAST_INTERVAL => Err(elapsed),
+            _ => Ok(last.replace(now)),
+        }
+    }
+
+    /// Gives back a slot whose rebroadcast failed, so it can be retried right away
+    fn release(&self, previous: Option<Instant>) {
+        *self.last.lock().expect("rebroadcast lock is poisoned") = previous;
+    }
+}
+
+/// Gossip traffic counters, updated by the swarm and read by `get_metrics`
+#[derive(Default)]
+struct Counters {
+    requests_sent: AtomicU64,
+    responses_sent: AtomicU64,
+    messages_received: AtomicU64,
+    duplicates_dropped: AtomicU64,
+    invalid_messages: AtomicU64,
+}
+
+impl Counters {
+    fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a message gossipsub delivered, by what `receive` made of it
+    fn received(&self, received: &Received) {
+        Self::increment(&self.messages_received);
+        match received {
+            Received::Duplicate => Self::increment(&self.duplicates_dropped),
+            Received::Parsed(None) => Self::increment(&self.invalid_messages),
+            _ => {}
+        }
+    }
+
+    fn snapshot(&self) -> Metrics {
+        Metrics {
+            requests_sent: self.requests_sent.load(Ordering::Relaxed),
+            responses_sent: self.responses_sent.load(Ordering::Relaxed),
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+            duplicates_dropped: self.duplicates_dropped.load(Ordering::Relaxed),
+            invalid_messages: self.invalid_messages.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counters as returned to the frontend
+#[derive(Debug, Serialize)]
+struct Metrics {
+    requests_sent: u64,
+    responses_sent: u64,
+    messages_received: u64,
+    duplicates_dropped: u64,
+    /// Messages that were neither a request nor a response
+    invalid_messages: u64,
+}
+
+#[derive(Default)]
+struct MetricsState {
+    counters: Arc<Counters>,
+}
+
+/// Last round-trip time measured by ping for each peer
+#[derive(Default)]
+struct LatenciesState {
+    latencies: Arc<Mutex<HashMap<PeerId, Duration>>>,
+}
+
+/// Peers that sent a `Presence` heartbeat recently
+struct PresenceState {
+    roster: Arc<Mutex<Roster>>,
+}
+
+/// Peers with at least one open connection, unlike `PeersState` which holds discovered ones.
+/// Gossip only reaches connected peers.
+#[derive(Default)]
+struct ConnectionsState {
+    peers: Arc<Mutex<HashSet<PeerId>>>,
+}
+
+fn list_known_peers(peers: &Mutex<HashSet<PeerId>>) -> Vec<String> {
+    peers
+        .lock()
+        .expect("peers lock is poisoned")
+        .iter()
+        .map(|p| p.to_string())
+        .collect()
+}
+
+/// Encoding of requests and responses on the wire, `json` (default) or `cbor`.
+/// CBOR is more compact, JSON is understood by every version of the app.
+const WIRE_FORMAT_ENV: &str = "VOTINGDAPP_WIRE_FORMAT";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WireFormat {
+    Json,
+    Cbor,
+}
+
+impl FromStr for WireFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(WireFormat::Json),
+            "cbor" => Ok(WireFormat::Cbor),
+            _ => Err(format!("unknown wire format: {}", s)),
+        }
+    }
+}
+
+impl WireFormat {
+    fn encode<T: Serialize>(self, message: &T) -> Result<Vec<u8>> {
+        match self {
+            WireFormat::Json => Ok(serde_json::to_vec(message)?),
+            WireFormat::Cbor => Ok(serde_cbor::to_vec(message)?),
+        }
+    }
+
+    fn decode<T: serde::de::DeserializeOwned>(self, data: &[u8]) -> Option<T> {
+        match self {
+            WireFormat::Json => serde_json::from_slice(data).ok(),
+            WireFormat::Cbor => serde_cbor::from_slice(data).ok(),
+        }
+    }
+}
+
+static WIRE_FORMAT: Lazy<WireFormat> = Lazy::new(|| env_or(WIRE_FORMAT_ENV, WireFormat::Json));
+
+/// A gossip payload once parsed
+#[derive(Debug)]
+enum IncomingMessage {
+    Response(ListResponse),
+    Request(ListRequest),
+    Presence(Presence),
+}
+
+/// Parses a gossip payload, independently of libp2p so it can be reasoned about on its own
+fn classify_message(data: &[u8]) -> Option<IncomingMessage> {
+    // the configured format first, peers configured differently are still understood
+    let formats = match *WIRE_FORMAT {
+        WireFormat::Json => [WireFormat::Json, WireFormat::Cbor],
+        WireFormat::Cbor => [WireFormat::Cbor, WireFormat::Json],
+    };
+    formats.iter().find_map(|format| {
+        if let Some(resp) = format.decode::<ListResponse>(data) {
+            Some(IncomingMessage::Response(resp))
+        } else if let Some(req) = format.decode::<ListRequest>(data) {
+            Some(IncomingMessage::Request(req))
+        } else {
+            format
+                .decode::<Presence>(data)
+                .map(IncomingMessage::Presence)
+        }
+    })
+}
+
+/// Whether a response sent to `receiver` is for `peer_id`, broadcast ones being for everyone
+fn is_addressed_to(receiver: &str, peer_id: &PeerId) -> bool {
+    receiver == BROADCAST_RECEIVER || receiver == peer_id.to_string()
+}
+
+/// Poll the votes of `resp`, received on `topic`, belong to. `Topic` responses can name any
+/// poll, they're only taken for a poll `query` asked for or one `is_joined`, so a peer can't
+/// make us store votes of as many polls as it likes. `None` when the votes must be dropped.
+fn response_poll<'a>(
+    resp: &'a ListResponse,
+    topic: &'a TopicHash,
+    query: Option<&ListRequest>,
+    is_joined: impl Fn(&str) -> bool,
+) -> Option<&'a str> {
+    match &resp.mode {
+        ListMode::Topic(poll) => {
+            let asked = matches!(
+                query,
+                Some(ListRequest { mode: ListMode::Topic(asked), .. }) if asked == poll
+            );
+            (asked || is_joined(poll)).then(|| poll.as_str())
+        }
+        _ => Some(topic.as_str()),
+    }
+}
+
+/// Marks `source` online as of `now` with the name it gave, returning `true` when the online
+/// peers changed. The receive time is used, a wrong clock on the sender's side can't keep it
+/// online.
+fn record_presence(roster: &mut Roster, source: PeerId, presence: &Presence, now: u64) -> bool {
+    // gossip messages are signed by their author, a heartbeat can't speak for someone else
+    if presence.peer_id != source.to_string() {
+        info!(
+            "Ignoring presence of {} relayed as {}",
+            presence.peer_id, source
+        );
+        return false;
+    }
+    debug!("Presence of {} at {}", source, presence.timestamp);
+    let label = presence
+        .display_name
+        .as_deref()
+        .and_then(presence::sanitize_label);
+    roster.record(source, now, label)
+}
+
+impl VoteBehaviour {
+    fn handle_presence(&mut self, source: PeerId, presence: Presence) {
+        let mut roster = self.roster.lock().expect("roster lock is poisoned");
+        if record_presence(&mut roster, source, &presence, now_millis()) {
+            emit_online_peers(&self.window, &roster);
+        }
+    }
+
+    fn handle_response(&mut self, source: PeerId, topic: &TopicHash, resp: ListResponse) {
+        if !is_addressed_to(&resp.receiver, &local_peer_id()) {
+            return;
+        }
+        let query = resp
+            .request_id
+            .as_deref()
+            .and_then(|request_id| self.queries.request(request_id));
+        let gossipsub = &self.gossipsub;
+        // votes of a `Topic` request belong to the requested poll, not the one it went through
+        let poll = match response_poll(&resp, topic, query, |poll| {
+            gossipsub.topics().any(|joined| joined.as_str() == poll)
+        }) {
+            Some(poll) => poll.to_owned(),
+            None => {
+                info!(
+                    "Dropping response from {} on {} for {:?}, neither asked for nor joined",
+                    source, topic, resp.mode
+                );
+                return;
+            }
+        };
+        if let Some(request_id) = &resp.request_id {
+            // cancelled or timed out, nobody is waiting for it anymore
+            if !self
+                .queries
+                .record_response(request_id, source, Instant::now())
+            {
+                info!(
+                    "Dropping response from {} on {} to unknown query {}",
+                    source, topic, request_id
+                );
+                return;
+            }
+        }
+        match resp.mode {
+            ListMode::ALL => info!("Response from {} on {}:", source, topic),
+            ListMode::One(_) => info!("Targeted response from {} on {}:", source, topic),
+            ListMode::Topic(ref poll) => {
+                info!("Response for poll {} from {} on {}:", poll, source, topic)
+            }
+        }
+        let (valid, forged): (Votes, Votes) =
+            resp.data.into_iter().partition(|v| verify_vote(v, &source));
+        forged.iter().for_each(|r| {
+            error!(
+                "Rejected vote with a bad signature from {} on {}: {:?}",
+                source, topic, r
+            )
+        });
+        valid.iter().for_each(|r| info!("{:?}", r));
+        if let Some(fetch) = resp
+            .request_id
+            .as_ref()
+            .and_then(|id| self.fetches.get_mut(id))
+        {
+            fetch.votes.extend(valid.iter().cloned());
+        }
+        let merged = match merge_remote_votes(&poll, &source.to_string(), valid) {
+            // only votes that weren't known yet are sent, so redelivered responses stay silent
+            Ok(added) if !added.is_empty() => {
+                if let Err(e) = self.window.emit(
+                    "vote_received",
+                    json!({ "poll": poll, "source": source.to_string(), "votes": added }),
+                ) {
+                    error!("error emitting vote_received event, {}", e);
+                }
+                added.len()
+            }
+            Ok(_) => 0,
+            Err(e) => {
+                error!("error merging votes from {} on {}: {}", source, topic, e);
+                0
+            }
+        };
+        if let Some(sync) = InitialSync::answered_by(&mut self.sync, resp.request_id.as_deref()) {
+            sync.record(source, merged);
+            if let Err(e) = self.window.emit("sync_progress", sync.progress()) {
+                error!("error emitting sync_progress event, {}", e);
+            }
+        }
+    }
+
+    fn handle_request(&mut self, source: PeerId, topic: &TopicHash, req: ListRequest) {
+        if !answers_peers(self.offline, *OBSERVER) {
+            info!("Not answering req from {} on {}", source, topic);
+            return;
+        }
+        info!("Received req: {:?} from {} on {}", req, source, topic);
+        match self.answers.request(source, &req, &local_peer_id()) {
+            Some(Answer {
+                receiver,
+                mode,
+                target,
+            }) => respond_with_public_votes(
+                self.response_sender.clone(),
+                topic.as_str().to_owned(),
+                receiver,
+                mode,
+                target,
+                req.request_id,
+            ),
+            None => info!(
+                "Ignoring req from {} on {}, not for us or answered recently",
+                source, topic
+            ),
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<GossipsubEvent> for VoteBehaviour {
+    fn inject_event(&mut self, event: GossipsubEvent) {
+        match event {
+            GossipsubEvent::Message {
+                propagation_source,
+                message,
+                ..
+            } => {
+                // messages are signed, so the author is always known
+                let source = message.source.unwrap_or(propagation_source);
+                let topic = &message.topic;
+                let received = receive(&mut self.seen_messages, &source, &message.data);
+                self.counters.received(&received);
+                match received {
+                    Received::Oversized => info!(
+                        "Dropping oversized message ({} bytes) from {} on {}",
+                        message.data.len(),
+                        source,
+                        topic
+                    ),
+                    Received::Duplicate => info!(
+                        "Dropping already handled message from {} on {}",
+                        source, topic
+                    ),
+                    Received::Parsed(Some(IncomingMessage::Response(resp))) => {
+                        self.handle_response(source, topic, resp)
+                    }
+                    Received::Parsed(Some(IncomingMessage::Request(req))) => {
+                        self.handle_request(source, topic, req)
+                    }
+                    Received::Parsed(Some(IncomingMessage::Presence(presence))) => {
+                        self.handle_presence(source, presence)
+                    }
+                    Received::Parsed(None) => {
+                        info!("Ignoring unknown message from {} on {}", source, topic)
+                    }
+                }
+            }
+            GossipsubEvent::Subscribed { peer_id, topic }
+                if !answers_peers(self.offline, *OBSERVER) =>
+            {
+                info!("{} subscribed to {}", peer_id, topic)
+            }
+            GossipsubEvent::Subscribed { peer_id, topic } => {
+                // late joiners get our votes right away instead of having to ask for them
+                match self.answers.subscription(peer_id) {
+                    Some(Answer {
+                        receiver,
+                        mode,
+                        target,
+                    }) => {
+                        info!("{} subscribed to {}, sending our votes", peer_id, topic);
+                        respond_with_public_votes(
+                            self.response_sender.clone(),
+                            topic.as_str().to_owned(),
+                            receiver,
+                            mode,
+                            target,
+                            None,
+                        );
+                    }
+                    None => info!(
+                        "{} subscribed to {}, our votes were sent recently",
+                        peer_id, topic
+                    ),
+                }
+            }
+            GossipsubEvent::Unsubscribed { peer_id, topic } => {
+                info!("{} unsubscribed from {}", peer_id, topic)
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The only votes allowed to leave this node: public ones, signed. Private votes must never
+/// end up in a response.
+fn shareable_votes(keys: &identity::Keypair, votes: Votes) -> Result<Votes> {
+    votes
+        .into_iter()
+        .filter(|r| r.public)
+        .map(|r| sign_vote(keys, r))
+        .collect()
+}
+
+fn respond_with_public_votes(
+    sender: mpsc::UnboundedSender<OutgoingResponse>,
+    poll: String,
+    receiver: String,
+    mode: ListMode,
+    target: ResponseTarget,
+    request_id: Option<String>,
+) {
+    tokio::spawn(async move {
+        // `poll` is where the response is published, a `Topic` request may ask for another one
+        let votes_poll = match &mode {
+            ListMode::Topic(requested) => requested.clone(),
+            _ => poll.clone(),
+        };
+        let data = read_poll_votes(&votes_poll)
+            .map_err(Into::into)
+            .and_then(|votes| shareable_votes(&node_keys(), votes));
+        match data {
+            Ok(data) => {
+                debug_assert!(data.iter().all(|r| r.public), "private vote in a response");
+                let resp = ListResponse {
+                    mode,
+                    receiver,
+                    data,
+                    request_id,
+                    nonce: None,
+                };
+                if let Err(e) = sender.send(OutgoingResponse {
+                    poll,
+                    response: resp,
+                    target,
+                }) {
+                    error!("error sending response via channel, {}", e);
+                }
+            }
+            Err(e) => error!("error fetching local votes to answer ALL request, {}", e),
+        }
+    });
+}
+
+/// Consecutive ping failures after which a peer is considered dead
+const MAX_PING_FAILURES: u32 = 3;
+
+impl NetworkBehaviourEventProcess<PingEvent> for VoteBehaviour {
+    fn inject_event(&mut self, event: PingEvent) {
+        let peer = event.peer;
+        match event.result {
+            Ok(PingSuccess::Ping { rtt }) => {
+                debug!("Ping to {} took {:?}", peer, rtt);
+                self.ping_failures.remove(&peer);
+                self.latencies
+                    .lock()
+                    .expect("latencies lock is poisoned")
+                    .insert(peer, rtt);
+            }
+            Ok(PingSuccess::Pong) => {}
+            Err(e) => {
+                let failures = self.ping_failures.entry(peer).or_insert(0);
+                *failures += 1;
+                info!("Ping to {} failed ({} in a row): {}", peer, failures, e);
+                if *failures >= MAX_PING_FAILURES {
+                    info!("Dropping unresponsive peer {}", peer);
+                    self.ping_failures.remove(&peer);
+                    self.peer_addrs.forget(&peer);
+                    self.latencies
+                        .lock()
+                        .expect("latencies lock is poisoned")
+                        .remove(&peer);
+                    self.gossipsub.remove_explicit_peer(&peer);
+                    let removed = self
+                        .peers
+                        .lock()
+                        .expect("peers lock is poisoned")
+                        .remove(&peer);
+                    if removed {
+                        self.emit_peers_changed();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<MdnsEvent> for VoteBehaviour {
+    fn inject_event(&mut self, event: MdnsEvent) {
+        match event {
+            MdnsEvent::Discovered(discovered_list) => {
+                let discovered_list: Vec<_> = discovered_list.collect();
+                remember_peers(&discovered_list);
+                let mut peers = self.peers.lock().expect("peers lock is poisoned");
+                for (peer, addr) in discovered_list {
+                    if self.peer_addrs.discovered(peer, addr) {
+                        peers.insert(peer);
+                        self.gossipsub.add_explicit_peer(&peer);
+                    }
+                }
+            }
+            MdnsEvent::Expired(expired_list) => {
+                let mut peers = self.peers.lock().expect("peers lock is poisoned");
+                for (peer, addr) in expired_list {
+                    if self.peer_addrs.expired(peer, &addr) {
+                        peers.remove(&peer);
+                        self.gossipsub.remove_explicit_peer(&peer);
+                    }
+                }
+            }
+        }
+        self.emit_peers_changed();
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn is_valid_language(name: &str) -> std::result::Result<bool, StorageError> {
+    Ok(known_languages()?.iter().any(|l| l.name == name))
+}
+
+/// Upper bound on stored votes, both local and remote. Every operation loads the whole
+/// file in memory, so this protects low-memory devices, and keeps a peer sending forged
+/// responses from filling our disk.
+const MAX_VOTES_ENV: &str = "VOTINGDAPP_MAX_VOTES";
+const DEFAULT_MAX_VOTES: usize = 10_000;
+
+fn max_votes() -> usize {
+    env_or(MAX_VOTES_ENV, DEFAULT_MAX_VOTES)
+}
+
+fn next_vote_id(votes: &Votes) -> usize {
+    match votes.iter().max_by_key(|r| r.id) {
+        Some(v) => v.id + 1,
+        None => 0,
+    }
+}
+
+/// Longest comment a vote can carry, in characters
+const MAX_COMMENT_CHARS: usize = 280;
+
+/// Held across every read-modify-write of a votes file, so two concurrent changes can't read
+/// the same content and have the last write lose the other's change
+static STORAGE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+fn lock_storage() -> MutexGuard<'static, ()> {
+    STORAGE_LOCK.lock().expect("storage lock is poisoned")
+}
+
+/// Why a vote couldn't be added, its message being shown to the user as is
+#[derive(Debug, Error)]
+enum AddVoteError {
+    #[error("already voted for {0}")]
+    Duplicate(String),
+    #[error("vote id {0} is used more than once")]
+    DuplicateId(usize),
+    #[error("unknown language {0}")]
+    UnknownLanguage(String),
+    #[error("comment is longer than {0} characters")]
+    CommentTooLong(usize),
+    #[error("weight {0} is out of range, it goes from 1 to 5")]
+    InvalidWeight(u8),
+    #[error("cannot store more than {0} votes")]
+    LimitExceeded(usize),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+/// Rules every local vote follows, whether added here or imported
+fn check_vote(
+    name: &str,
+    comment: Option<&str>,
+    weight: u8,
+) -> std::result::Result<(), AddVoteError> {
+    if !is_valid_language(name)? {
+        return Err(AddVoteError::UnknownLanguage(name.to_owned()));
+    }
+    if !(MIN_WEIGHT..=MAX_WEIGHT).contains(&weight) {
+        return Err(AddVoteError::InvalidWeight(weight));
+    }
+    if let Some(comment) = comment {
+        if comment.chars().count() > MAX_COMMENT_CHARS {
+            return Err(AddVoteError::CommentTooLong(MAX_COMMENT_CHARS));
+        }
+    }
+    Ok(())
+}
+
+fn add_vote(
+    poll: &str,
+    name: &str,
+    comment: Option<&str>,
+    weight: u8,
+) -> std::result::Result<Vote, AddVoteError> {
+    check_vote(name, comment, weight)?;
+    let _storage = lock_storage();
+    let mut storage = read_poll_storage(poll)?;
+    if storage.votes.len() >= max_votes() {
+        return Err(AddVoteError::LimitExceeded(max_votes()));
+    }
+    if storage.votes.iter().any(|r| r.name == name) {
+        return Err(AddVoteError::Duplicate(name.to_owned()));
+    }
+    let vote = Vote {
+        id: storage.allocate_id(),
+        name: name.to_owned(),
+        public: false,
+        created_at: now_millis(),
+        signature: vec![],
+        comment: comment.map(str::to_owned),
+        weight,
+        expires_at: None,
+    };
+    storage.votes.push(vote.clone());
+    write_poll_storage(poll, &storage)?;
+    record_audit(AuditEvent::new(
+        AuditOperation::Add,
+        poll,
+        Some(vote.id),
+        Some(name),
+    ));
+
+    // logs can end up in a file, which unlike the votes file is never encrypted
+    info!("Added vote {} to {}", vote.id, poll);
+
+    Ok(vote)
+}
+
+/// Why a stored vote couldn't be changed, its message being shown to the user as is
+#[derive(Debug, Error)]
+enum ChangeVoteError {
+    #[error("no vote with id {0}")]
+    NotFound(usize),
+    #[error("already voted for {0}")]
+    Duplicate(String),
+    #[error("unknown language {0}")]
+    UnknownLanguage(String),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+fn set_visibility(poll: &str, id: usize, public: bool) -> std::result::Result<(), ChangeVoteError> {
+    let _storage = lock_storage();
+    let mut local_votes = read_poll_votes(poll)?;
+    let vote = local_votes
+        .iter_mut()
+        .find(|r| r.id == id)
+        .ok_or(ChangeVoteError::NotFound(id))?;
+    vote.public = public;
+    let name = vote.name.clone();
+    write_poll_votes(poll, &local_votes)?;
+    let operation = match public {
+        true => AuditOperation::Publish,
+        false => AuditOperation::Unpublish,
+    };
+    record_audit(AuditEvent::new(operation, poll, Some(id), Some(&name)));
+    Ok(())
+}
+
+/// Makes the votes with the given ids public in a single write, returning the ids of those that
+/// were private. Unknown ids are ignored.
+fn publish_local_votes(poll: &str, ids: &[usize]) -> std::result::Result<Vec<usize>, StorageError> {
+    let _storage = lock_storage();
+    let mut local_votes = read_poll_votes(poll)?;
+    let mut published = vec![];
+    for vote in local_votes
+        .iter_mut()
+        .filter(|r| !r.public && ids.contains(&r.id))
+    {
+        vote.public = true;
+        published.push((vote.id, vote.name.clone()));
+    }
+    if published.is_empty() {
+        return Ok(vec![]);
+    }
+    write_poll_votes(poll, &local_votes)?;
+    for (id, name) in &published {
+        record_audit(AuditEvent::new(
+            AuditOperation::Publish,
+            poll,
+            Some(*id),
+            Some(name),
+        ));
+    }
+    Ok(published.into_iter().map(|(id, _)| id).collect())
+}
+
+/// Sets or clears (`None`) the time after which a vote is deleted
+fn set_expiry(
+    poll: &str,
+    id: usize,
+    expires_at: Option<u64>,
+) -> std::result::Result<(), ChangeVoteError> {
+    let _storage = lock_storage();
+    let mut local_votes = read_poll_votes(poll)?;
+    let vote = local_votes
+        .iter_mut()
+        .find(|r| r.id == id)
+        .ok_or(ChangeVoteError::NotFound(id))?;
+    vote.expires_at = expires_at;
+    let name = vote.name.clone();
+    write_poll_votes(poll, &local_votes)?;
+    record_audit(AuditEvent::new(
+        AuditOperation::SetExpiry,
+        poll,
+        Some(id),
+        Some(&name),
+    ));
+    Ok(())
+}
+
+/// Splits `votes` into the ones expired at `now` and the others. Votes without an expiry
+/// are always kept.
+fn split_expired(votes: Votes, now: u64) -> (Votes, Votes) {
+    votes
+        .into_iter()
+        .partition(|r| r.expires_at.map_or(false, |expires_at| expires_at <= now))
+}
+
+/// Deletes the expired local votes of `poll`, returning how many were
+fn prune_expired_votes(poll: &str, now: u64) -> Result<usize> {
+    let _storage = lock_storage();
+    let (expired, kept) = split_expired(read_poll_votes(poll)?, now);
+    if expired.is_empty() {
+        return Ok(0);
+    }
+    write_poll_votes(poll, &kept)?;
+    for vote in &expired {
+        record_audit(AuditEvent::new(
+            AuditOperation::Expire,
+            poll,
+            Some(vote.id),
+            Some(&vote.name),
+        ));
+    }
+    Ok(expired.len())
+}
+
+/// Changes the language of a vote, keeping its id and creation time
+fn rename_local_vote(
+    poll: &str,
+    id: usize,
+    new_name: &str,
+) -> std::result::Result<(), ChangeVoteError> {
+    if !is_valid_language(new_name)? {
+        return Err(ChangeVoteError::UnknownLanguage(new_name.to_owned()));
+    }
+    let _storage = lock_storage();
+    let mut local_votes = read_poll_votes(poll)?;
+    if local_votes.iter().any(|r| r.id != id && r.name == new_name) {
+        return Err(ChangeVoteError::Duplicate(new_name.to_owned()));
+    }
+    local_votes
+        .iter_mut()
+        .find(|r| r.id == id)
+        .ok_or(ChangeVoteError::NotFound(id))?
+        .name = new_name.to_owned();
+    write_poll_votes(poll, &local_votes)?;
+    record_audit(AuditEvent::new(
+        AuditOperation::Rename,
+        poll,
+        Some(id),
+        Some(new_name),
+    ));
+    Ok(())
+}
+
+/// Removes every local vote for `name`, returning how many were removed
+fn delete_votes_named(poll: &str, name: &str) -> Result<usize> {
+    let _storage = lock_storage();
+    let local_votes = read_poll_votes(poll)?;
+    let (removed, kept): (Votes, Votes) = local_votes.into_iter().partition(|r| r.name == name);
+    if removed.is_empty() {
+        return Ok(0);
+    }
+    write_poll_votes(poll, &kept)?;
+    for vote in &removed {
+        record_audit(AuditEvent::new(
+            AuditOperation::Delete,
+            poll,
+            Some(vote.id),
+            Some(&vote.name),
+        ));
+    }
+    Ok(removed.len())
+}
+
+/// Append-only JSON lines file recording every change to local votes
+const AUDIT_FILE_NAME: &str = "audit.log";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AuditOperation {
+    Add,
+    Publish,
+    Unpublish,
+    Rename,
+    Delete,
+    Reset,
+    SetExpiry,
+    Expire,
+    Import,
+}
+
+/// One line of `audit.log`
+#[derive(Debug, Serialize, Deserialize)]
+struct AuditEvent {
+    operation: AuditOperation,
+    poll: String,
+    /// Absent for operations on every vote at once, like a reset
+    vote_id: Option<usize>,
+    /// Also absent when `STORAGE_KEY_ENV` is set: the audit log isn't encrypted, and names would
+    /// give away the private votes
+    name: Option<String>,
+    peer_id: String,
+    /// Unix timestamp, in seconds
+    timestamp: u64,
+}
+
+impl AuditEvent {
+    fn new(
+        operation: AuditOperation,
+        poll: &str,
+        vote_id: Option<usize>,
+        name: Option<&str>,
+    ) -> Self {
+        Self {
+            operation,
+            poll: poll.to_owned(),
+            vote_id,
+            name: name.filter(|_| STORAGE_KEY.is_none()).map(str::to_owned),
+            peer_id: local_peer_id().to_string(),
+            timestamp: now_millis() / 1000,
+        }
+    }
+}
+
+fn append_audit(event: &AuditEvent) -> Result<()> {
+    let mut line = serde_json::to_string(event)?;
+    line.push('\n');
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(get_data_file_path(AUDIT_FILE_NAME)?)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// The change itself already happened, so a failure to audit it is only logged
+fn record_audit(event: AuditEvent) {
+    if let Err(e) = append_audit(&event) {
+        error!("error recording {:?} in the audit log: {}", event, e);
+    }
+}
+
+/// Most recent audit entries, oldest first. Unreadable lines are skipped.
+fn read_audit_entries(limit: usize) -> Result<Vec<AuditEvent>> {
+    let content = match fs::read_to_string(get_data_file_path(AUDIT_FILE_NAME)?) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut entries: Vec<AuditEvent> = content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let skip = entries.len().saturating_sub(limit);
+    Ok(entries.split_off(skip))
+}
+
+const STORAGE_VERSION: u32 = 2;
+
+/// On-disk layout of `votes.json`
+#[derive(Debug, Serialize, Deserialize)]
+struct Storage {
+    version: u32,
+    votes: Votes,
+    /// Id given to the next vote, only ever increasing so ids of deleted votes aren't reused.
+    /// Added in version 2.
+    #[serde(default)]
+    next_id: usize,
+}
+
+impl Storage {
+    fn allocate_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+}
+
+/// Every layout `votes.json` has had, the oldest being a bare array of votes
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum StoredVotes {
+    V0(Votes),
+    Versioned(Storage),
+}
+
+fn migrate_storage(stored: StoredVotes) -> std::result::Result<Storage, StorageError> {
+    match stored {
+        StoredVotes::V0(votes) => Ok(Storage {
+            version: STORAGE_VERSION,
+            next_id: next_vote_id(&votes),
+            votes,
+        }),
+        StoredVotes::Versioned(storage) if storage.version <= STORAGE_VERSION => Ok(Storage {
+            version: STORAGE_VERSION,
+            // older versions had no counter, carry on from the highest id
+            next_id: storage.next_id.max(next_vote_id(&storage.votes)),
+            votes: storage.votes,
+        }),
+        StoredVotes::Versioned(storage) => Err(StorageError::Corrupt(format!(
+            "unsupported storage version {}",
+            storage.version
+        ))),
+    }
+}
+
+fn read_local_votes() -> std::result::Result<Votes, StorageError> {
+    read_poll_votes(&TOPIC_NAME)
+}
+
+fn write_local_votes(votes: &Votes) -> std::result::Result<(), StorageError> {
+    write_poll_votes(&TOPIC_NAME, votes)
+}
+
+fn empty_storage() -> Storage {
+    Storage {
+        version: STORAGE_VERSION,
+        votes: vec![],
+        next_id: 0,
+    }
+}
+
+/// Secret encrypting the local votes files, which hold private votes. Without it they are
+/// written in plaintext, and either kind is read. With it, vote names are also kept out of
+/// the audit log and the logs.
+const STORAGE_KEY_ENV: &str = "VOTINGDAPP_STORAGE_KEY";
+
+static STORAGE_KEY: Lazy<Option<at_rest::Secret>> = Lazy::new(|| {
+    env::var(STORAGE_KEY_ENV)
+        .ok()
+        .filter(|secret| !secret.is_empty())
+        .map(|secret| at_rest::Secret::new(&secret))
+});
+
+/// Parses the content of a local votes file, encrypted or not
+fn parse_storage(content: &[u8]) -> std::result::Result<Storage, StorageError> {
+    let json = at_rest::open(STORAGE_KEY.as_ref(), content).map_err(StorageError::Corrupt)?;
+    migrate_storage(serde_json::from_slice(&json)?)
+}
+
+fn read_poll_storage(poll: &str) -> std::result::Result<Storage, StorageError> {
+    let path = poll_storage_file_path(poll)?;
+    let content = match fs::read(&path) {
+        Ok(content) => content,
+        Err(_e) => return Ok(empty_storage()),
+    };
+    // a missing or wrong key is an error, the file isn't corrupt
+    let json = at_rest::open(STORAGE_KEY.as_ref(), &content).map_err(StorageError::Corrupt)?;
+    match serde_json::from_slice::<StoredVotes>(&json) {
+        Ok(stored) => migrate_storage(stored),
+        Err(e) => {
+            // keep the app usable, but don't throw the user's data away
+            error!("{} is corrupt, moving it aside: {}", path, e);
+            fs::rename(&path, format!("{}.corrupt", path))?;
+            Ok(empty_storage())
+        }
+    }
+}
+
+fn read_poll_votes(poll: &str) -> std::result::Result<Votes, StorageError> {
+    Ok(read_poll_storage(poll)?.votes)
+}
+
+/// Writes `content` to a sibling temporary file, waits for it to reach the disk, then renames
+/// it over `path`. A crash leaves either the previous content or the new one, never a truncated
+/// file, and a vote added right before quitting survives a power loss.
+fn write_synced(path: impl AsRef<Path>, content: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let written = fs::File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    // the rename itself only survives a power loss once the directory is synced
+    #[cfg(unix)]
+    {
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Failures reading or writing the data files, its message being shown to the user as is
+#[derive(Debug, Error)]
+enum StorageError {
+    #[error("cannot access the data files: {0}")]
+    Io(#[from] io::Error),
+    #[error("cannot read or write the data files: {0}")]
+    Serde(#[from] serde_json::Error),
+    /// Unreadable content, including an encrypted file and a missing or wrong key
+    #[error("stored votes are unreadable: {0}")]
+    Corrupt(String),
+    #[error("cannot write {path}, check the permissions of the data directory")]
+    PermissionDenied { path: String },
+    #[error("no directory to store data in, set VOTINGDAPP_STORAGE_PATH")]
+    NoDataDir,
+}
+
+fn storage_write_error(path: &str, e: io::Error) -> StorageError {
+    match e.kind() {
+        io::ErrorKind::PermissionDenied => StorageError::PermissionDenied {
+            path: path.to_owned(),
+        },
+        _ => StorageError::Io(e),
+    }
+}
+
+/// Replaces the votes of `poll`, keeping its id counter
+fn write_poll_votes(poll: &str, votes: &Votes) -> std::result::Result<(), StorageError> {
+    let next_id = read_poll_storage(poll)?.next_id.max(next_vote_id(votes));
+    write_poll_storage(
+        poll,
+        &Storage {
+            version: STORAGE_VERSION,
+            votes: votes.clone(),
+            next_id,
+        },
+    )
+}
+
+/// Number of previous versions of a votes file kept as `<votes file>.1`, `.2`, ...
+const BACKUPS_ENV: &str = "VOTINGDAPP_BACKUPS";
+const DEFAULT_BACKUPS: usize = 3;
+
+fn backup_count() -> usize {
+    env_or(BACKUPS_ENV, DEFAULT_BACKUPS)
+}
+
+fn backup_path(path: &str, index: usize) -> String {
+    format!("{}.{}", path, index)
+}
+
+/// Shifts `path.1` to `path.2` and so on, dropping the oldest, then copies `path` to `path.1`.
+/// Nothing happens before the file is first written.
+fn rotate_backups(path: &str, count: usize) -> io::Result<()> {
+    if count == 0 || !Path::new(path).exists() {
+        return Ok(());
+    }
+    for index in (1..count).rev() {
+        let from = backup_path(path, index);
+        if Path::new(&from).exists() {
+            fs::rename(&from, backup_path(path, index + 1))?;
+        }
+    }
+    // copied rather than moved, so the votes file never goes missing if the write fails
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+fn write_poll_storage(poll: &str, storage: &Storage) -> std::result::Result<(), StorageError> {
+    let json = serde_json::to_vec(storage)?;
+    let content = match STORAGE_KEY.as_ref() {
+        Some(key) => {
+            at_rest::seal(key, &json).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        }
+        None => json,
+    };
+
+    let path = poll_storage_file_path(poll)?;
+    if let Err(e) = rotate_backups(&path, backup_count()) {
+        // a missing backup shouldn't keep the vote from being saved
+        error!("error rotating backups of {}: {}", path, e);
+    }
+    write_synced(&path, &content).map_err(|e| storage_write_error(&path, e))?;
+    notify_votes_changed(poll, true, storage.votes.clone());
+    Ok(())
+}
+
+/// Swaps backup `index` of `poll` with its votes file, so restoring twice undoes the restore
+fn restore_poll_backup(poll: &str, index: usize) -> Result<Votes> {
+    if index == 0 || index > backup_count() {
+        return Err(format!(
+            "no backup number {}, backups go from 1 to {}",
+            index,
+            backup_count()
+        )
+        .into());
+    }
+    let path = poll_storage_file_path(poll)?;
+    let backup = backup_path(&path, index);
+    let content = fs::read(&backup).map_err(|e| format!("cannot read {}: {}", backup, e))?;
+    let storage = parse_storage(&content)?;
+
+    let _storage = lock_storage();
+    let swap = format!("{}.swap", path);
+    if Path::new(&path).exists() {
+        fs::rename(&path, &swap)?;
+    }
+    fs::rename(&backup, &path)?;
+    if Path::new(&swap).exists() {
+        fs::rename(&swap, &backup)?;
+    }
+    info!("Restored {} from {}", path, backup);
+    notify_votes_changed(poll, true, storage.votes.clone());
+    Ok(storage.votes)
+}
+
+/// Latest change to the stored votes, forwarded to the frontend by `watch_votes`
+#[derive(Debug, Clone, Serialize)]
+struct VotesUpdate {
+    poll: String,
+    /// Whether `votes` are this node's votes, or every remote vote of the poll
+    local: bool,
+    votes: Votes,
+}
+
+static VOTES_WATCH: Lazy<watch::Sender<Option<VotesUpdate>>> = Lazy::new(|| watch::channel(None).0);
+
+fn notify_votes_changed(poll: &str, local: bool, votes: Votes) {
+    // kept even without watchers, unlike `send`
+    VOTES_WATCH.send_replace(Some(VotesUpdate {
+        poll: poll.to_owned(),
+        local,
+        votes,
+    }));
+}
+
+fn read_remote_votes(poll: &str) -> Result<RemoteVotes> {
+    match fs::read(poll_remote_storage_file_path(poll)?) {
+        Ok(votes) => Ok(serde_json::from_slice(&votes)?),
+        Err(_e) => Ok(HashMap::new()),
+    }
+}
+
+fn write_remote_votes(poll: &str, votes: &RemoteVotes) -> Result<()> {
+    let json = serde_json::to_string(&votes)?;
+
+    let path = poll_remote_storage_file_path(poll)?;
+    fs::write(&path, &json).map_err(|e| storage_write_error(&path, e))?;
+    notify_votes_changed(poll, false, votes.values().flatten().cloned().collect());
+    Ok(())
+}
+
+/// How far in the future, in seconds, a remote vote can be dated before being clamped to the
+/// time it was received. Keeps a peer with a wrong clock from sorting its votes first forever.
+const MAX_FUTURE_SKEW_ENV: &str = "VOTINGDAPP_MAX_FUTURE_SKEW_SECS";
+const DEFAULT_MAX_FUTURE_SKEW_SECS: u64 = 5 * 60;
+
+fn max_future_skew_millis() -> u64 {
+    env_or(MAX_FUTURE_SKEW_ENV, DEFAULT_MAX_FUTURE_SKEW_SECS).saturating_mul(1000)
+}
+
+/// Stores votes received from `source`, replacing the ones already known by id.
+/// Returns the votes that weren't known yet.
+fn merge_remote_votes(poll: &str, source: &str, votes: Votes) -> Result<Votes> {
+    // our own votes only ever live in the local file
+    if source == local_peer_id().to_string() {
+        return Err("refusing to store our own votes as remote ones".into());
+    }
+    let _storage = lock_storage();
+    let mut remote_votes = read_remote_votes(poll)?;
+    let max = max_votes();
+    let mut total: usize = remote_votes.values().map(Vec::len).sum();
+    let known = remote_votes.entry(source.to_owned()).or_default();
+    let mut added = vec![];
+    let now = now_millis();
+    let max_skew = max_future_skew_millis();
+    for mut vote in votes {
+        // would count several times over in weighted tallies
+        if !(MIN_WEIGHT..=MAX_WEIGHT).contains(&vote.weight) {
+            warn!(
+                "Dropping vote {} from {} with out of range weight {}",
+                vote.id, source, vote.weight
+            );
+            continue;
+        }
+        // the signature was checked already, only the stored copy is corrected
+        if vote.created_at > now.saturating_add(max_skew) {
+            warn!(
+                "Vote {} from {} is dated {}ms in the future, using the receive time instead",
+                vote.id,
+                source,
+                vote.created_at - now
+            );
+            vote.created_at = now;
+        }
+        match known.iter_mut().find(|r| r.id == vote.id) {
+            Some(existing) => *existing = vote,
+            None if total >= max => {
+                error!("remote votes limit of {} reached, dropping {:?}", max, vote)
+            }
+            None => {
+                total += 1;
+                known.push(vote.clone());
+                added.push(vote);
+            }
+        }
+    }
+    write_remote_votes(poll, &remote_votes)?;
+    Ok(added)
+}
+
+/// Peers discovered through mDNS, each listed once
+fn handle_list_peers(swarm: &Swarm<VoteBehaviour>) -> Vec<PeerId> {
+    let mut unique_peers = HashSet::new();
+    if let Some(mdns) = swarm.behaviour().mdns.as_ref() {
+        for peer in mdns.discovered_nodes() {
+            unique_peers.insert(*peer);
+        }
+    }
+    unique_peers.into_iter().collect()
+}
+
+fn publish_request(swarm: &mut Swarm<VoteBehaviour>, poll: &str, payload: &[u8]) {
+    let behaviour = swarm.behaviour_mut();
+    let sent = &behaviour.counters.requests_sent;
+    if let Err(e) = publish_counted(&mut behaviour.gossipsub, sent, poll, payload, *DRY_RUN) {
+        error!("error publishing request: {}", e);
+    }
+}
+
+/// Publishes `payload` on `poll`, counting it in `sent` once gossipsub took it. A `dry_run`
+/// only logs what would have been sent.
+fn publish_counted(
+    gossipsub: &mut Gossipsub,
+    sent: &AtomicU64,
+    poll: &str,
+    payload: &[u8],
+    dry_run: bool,
+) -> std::result::Result<(), String> {
+    if dry_run {
+        info!(
+            "Dry run, not publishing to {}: {}",
+            poll,
+            String::from_utf8_lossy(payload)
+        );
+        return Ok(());
+    }
+    gossipsub
+        .publish(IdentTopic::new(poll), payload)
+        .map_err(|e| format!("{:?}", e))?;
+    Counters::increment(sent);
+    Ok(())
+}
+
+fn emit_online_peers(window: &Window, roster: &Roster) {
+    if let Err(e) = window.emit("online_peers", roster.online(now_millis())) {
+        error!("error emitting online_peers event, {}", e);
+    }
+}
+
+/// Deletes expired votes of the default poll and of every joined one
+fn prune_expired(swarm: &Swarm<VoteBehaviour>, window: &Window) {
+    let mut polls: HashSet<String> = swarm
+        .behaviour()
+        .gossipsub
+        .topics()
+        .map(|topic| topic.as_str().to_owned())
+        .collect();
+    polls.insert(TOPIC_NAME.clone());
+    let now = now_millis();
+    for poll in polls {
+        match prune_expired_votes(&poll, now) {
+            Ok(0) => {}
+            Ok(count) => {
+                info!("Deleted {} expired votes of {}", count, poll);
+                if let Err(e) = emit_votes(window, &poll) {
+                    error!("error emitting get_votes event, {}", e);
+                }
+            }
+            Err(e) => error!("error deleting expired votes of {}: {}", poll, e),
+        }
+    }
+}
+
+/// Publishes our heartbeat and forgets peers that stopped sending theirs
+fn send_heartbeat(swarm: &mut Swarm<VoteBehaviour>, window: &Window) {
+    if swarm.behaviour().offline {
+        return;
+    }
+    let presence = Presence {
+        peer_id: local_peer_id().to_string(),
+        timestamp: now_millis(),
+        display_name: DISPLAY_NAME.clone(),
+    };
+    let behaviour = swarm.behaviour_mut();
+    match WIRE_FORMAT.encode(&presence) {
+        Ok(_) if *DRY_RUN => debug!("Dry run, not publishing presence {:?}", presence),
+        Ok(payload) => {
+            // fails with no peers around, which is expected when alone on the network
+            if let Err(e) = behaviour.gossipsub.publish(TOPIC.clone(), payload) {
+                debug!("error publishing presence: {:?}", e);
+            }
+        }
+        Err(e) => error!("error encoding presence: {}", e),
+    }
+    let mut roster = behaviour.roster.lock().expect("roster lock is poisoned");
+    let expired = roster.expire(now_millis());
+    if !expired.is_empty() {
+        info!(
+            "No presence from {:?} lately, considering them offline",
+            expired
+        );
+        emit_online_peers(window, &roster);
+    }
+}
+
+fn handle_query_updates(swarm: &mut Swarm<VoteBehaviour>, window: &Window) {
+    let updates = swarm.behaviour_mut().queries.poll_updates(Instant::now());
+    for update in updates {
+        match update {
+            QueryUpdate::Retry {
+                request_id,
+                poll,
+                mut request,
+            } => {
+                info!("No answer to query {}, retrying", request_id);
+                // same request id, so answers to either attempt count
+                request.attempt += 1;
+                let payload = WIRE_FORMAT.encode(&request).expect("cannot encode request");
+                publish_request(swarm, &poll, &payload);
+            }
+            QueryUpdate::Complete {
+                request_id,
+                peers_responded,
+            } => {
+                info!(
+                    "Query {} complete, {} peers answered",
+                    request_id, peers_responded
+                );
+                let behaviour = swarm.behaviour_mut();
+                if let Some(sync) = InitialSync::finish(&mut behaviour.sync, &request_id) {
+                    if let Err(e) = window.emit("sync_complete", sync.progress()) {
+                        error!("error emitting sync_complete event, {}", e);
+                    }
+                }
+                if let Some(fetch) = swarm.behaviour_mut().fetches.remove(&request_id) {
+                    fetch.complete(&request_id, peers_responded);
+                }
+                if let Err(e) = window.emit(
+                    "query_complete",
+                    json!({
+                        "request_id": request_id,
+                        "peers_responded": peers_responded,
+                    }),
+                ) {
+                    error!("error emitting query_complete event, {}", e);
+                }
+            }
+        }
+    }
+}
+
+fn subscribed_peers_of(gossipsub: &Gossipsub, poll: &str) -> Vec<String> {
+    let topic = IdentTopic::new(poll).hash();
+    gossipsub
+        .all_peers()
+        .filter(|(_, topics)| topics.contains(&&topic))
+        .map(|(peer, _)| peer.to_string())
+        .collect()
+}
+
+fn cancel_query_by_id(swarm: &mut Swarm<VoteBehaviour>, request_id: &str) -> bool {
+    let behaviour = swarm.behaviour_mut();
+    if !behaviour.queries.cancel(request_id) {
+        return false;
+    }
+    info!("Cancelled query {}", request_id);
+    if let Some(fetch) = behaviour.fetches.remove(request_id) {
+        // the caller may have gone away, nothing to do then
+        let _ = fetch
+            .reply
+            .send(Err(format!("query {} was cancelled", request_id)));
+    }
+    InitialSync::finish(&mut behaviour.sync, request_id);
+    true
+}
+
+/// Asks every peer for their votes at startup, tracking the responses as the initial sync
+fn start_initial_sync(swarm: &mut Swarm<VoteBehaviour>, window: &Window) {
+    let request_id = start_query(swarm, &TOPIC_NAME, ListMode::ALL);
+    if let Err(e) = window.emit("sync_started", json!({ "request_id": request_id })) {
+        error!("error emitting sync_started event, {}", e);
+    }
+    swarm.behaviour_mut().sync = Some(InitialSync::new(request_id));
+}
+
+/// Publishes a request on `poll` and tracks it as a query, returning its request id
+fn start_query(swarm: &mut Swarm<VoteBehaviour>, poll: &str, mode: ListMode) -> String {
+    let request_id = next_request_id();
+    let req = ListRequest {
+        mode,
+        request_id: Some(request_id.clone()),
+        attempt: 0,
+    };
+    let payload = WIRE_FORMAT.encode(&req).expect("cannot encode request");
+    publish_request(swarm, poll, &payload);
+    swarm
+        .behaviour_mut()
+        .queries
+        .start(request_id.clone(), poll.to_owned(), req, Instant::now());
+    request_id
+}
+
+fn fetch_votes_from(
+    swarm: &mut Swarm<VoteBehaviour>,
+    peer_id: PeerId,
+    poll: &str,
+    reply: oneshot::Sender<std::result::Result<Votes, String>>,
+) {
+    let request_id = start_query(swarm, poll, ListMode::One(peer_id.to_string()));
+    swarm.behaviour_mut().fetches.insert(
+        request_id,
+        PendingFetch {
+            reply,
+            votes: vec![],
+        },
+    );
+}
+
+fn handle_list_local_votes() -> Result<Votes> {
+    Ok(read_local_votes()?)
+}
+
+/// Commands typed on stdin
+#[derive(Debug, PartialEq)]
+enum Command {
+    /// `ls p`
+    ListPeers,
+    /// `ls v all`
+    ListVotesAll,
+    /// `ls v <peer_id>`
+    ListVotesFrom(String),
+    /// `ls v`
+    ListLocal,
+}
+
+/// Parses a peer id received as a string, so malformed ones are reported instead of never matching
+fn parse_peer_id(peer_id: &str) -> std::result::Result<PeerId, String> {
+    PeerId::from_str(peer_id.trim()).map_err(|e| format!("invalid peer id {}: {}", peer_id, e))
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["ls", "p"] => Ok(Command::ListPeers),
+            ["ls", "v"] => Ok(Command::ListLocal),
+            ["ls", "v", "all"] => Ok(Command::ListVotesAll),
+            ["ls", "v", peer_id] => Ok(Command::ListVotesFrom(parse_peer_id(peer_id)?.to_string())),
+            _ => Err(format!("unknown command: {}", s.trim())),
+        }
+    }
+}
+
+fn handle_command(command: Command, swarm: &mut Swarm<VoteBehaviour>) {
+    match command {
+        Command::ListPeers => {
+            info!("Discovered Peers:");
+            handle_list_peers(swarm).iter().for_each(|p| info!("{}", p));
+        }
+        Command::ListVotesAll => {
+            start_query(swarm, &TOPIC_NAME, ListMode::ALL);
+        }
+        Command::ListVotesFrom(peer_id) => {
+            start_query(swarm, &TOPIC_NAME, ListMode::One(peer_id));
+        }
+        Command::ListLocal => match handle_list_local_votes() {
+            // logs can end up in a file, which unlike the votes file is never encrypted
+            Ok(v) => {
+                info!("Local Votes ({})", v.len());
+                v.iter().for_each(|r| {
+                    info!("{} {}", r.id, if r.public { "public" } else { "private" })
+                });
+                v.iter()
+                    .filter(|r| r.public)
+                    .for_each(|r| debug!("{:?}", r));
+            }
+            Err(e) => error!("error fetching local votes: {}", e),
+        },
+    }
+}
+
+/// Forwards commands typed on stdin to the swarm loop, until stdin is closed
+async fn read_stdin_commands(commands: mpsc::UnboundedSender<SwarmCommand>) {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        match line.parse::<Command>() {
+            Ok(command) => {
+                if commands.send(SwarmCommand::Cli(command)).is_err() {
+                    break;
+                }
+            }
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+/// Reports a failure to the frontend through an `error` event so it can show a toast
+fn emit_error(window: &Window, context: &str, message: &str) {
+    error!("{}: {}", context, message);
+    if let Err(e) = window.emit(
+        "error",
+        json!({
+            "context": context,
+            "message": message,
+        }),
+    ) {
+        error!("error emitting error event, {}", e);
+    }
+}
+
+/// Emits the `error` event and turns the failure into a command error
+fn command_error(window: &Window, context: &str, e: impl std::fmt::Display) -> tauri::Error {
+    let message = e.to_string();
+    emit_error(window, context, &message);
+    tauri::Error::Io(io::Error::new(io::ErrorKind::Other, message))
+}
+
+/// Sends the current votes of `poll` to the frontend. Mutating commands call it once they are
+/// done, never from inside a loop, so bulk changes don't flood the frontend.
+fn emit_votes(window: &Window, poll: &str) -> tauri::Result<()> {
+    let votes = read_poll_votes(poll).map_err(|e| command_error(window, "read_local_votes", e))?;
+    window.emit(
+        "get_votes",
+        json!({
+            "poll": poll,
+            "votes": votes,
+        }),
+    )
+}
+
+/// Fails commands changing votes when running as an observer
+fn reject_in_observer_mode(window: &Window, context: &str) -> tauri::Result<()> {
+    check_can_change_votes(*OBSERVER).map_err(|e| command_error(window, context, e))
+}
+
+/// Commands act on the default poll unless told otherwise
+fn poll_or_default(poll: Option<String>) -> String {
+    poll.unwrap_or_else(|| TOPIC_NAME.clone())
+}
+
+/// A double click on "vote" stores the vote once: `add_vote` checks for the name under the
+/// storage lock, so the second call fails with `AddVoteError::Duplicate`
+#[tauri::command]
+fn on_publish_vote(
+    name: String,
+    comment: Option<String>,
+    weight: Option<u8>,
+    poll: Option<String>,
+    window: Window,
+    state: State<SenderState>,
+) -> tauri::Result<()> {
+    reject_in_observer_mode(&window, "add_vote")?;
+    let poll = poll_or_default(poll);
+    add_vote(
+        &poll,
+        name.as_str(),
+        comment.as_deref(),
+        weight.unwrap_or(MIN_WEIGHT),
+    )
+    .map_err(|e| command_error(&window, "add_vote", e))?;
+
+    let cloned_state = state.sender.clone();
+    let cloned_poll = poll.clone();
+
+    tauri::async_runtime::spawn(async move {
+        respond_with_public_votes(
+            cloned_state,
+            cloned_poll,
+            BROADCAST_RECEIVER.to_owned(),
+            ListMode::ALL,
+            ResponseTarget::Topic,
+            None,
+        );
+    });
+
+    emit_votes(&window, &poll)?;
+
+    Ok(())
+}
+
+/// How `tally_votes` scores a language
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+enum TallyMode {
+    /// One point per vote
+    Count,
+    /// The sum of the weights of the votes
+    Weighted,
+}
+
+/// Counts votes per language, most voted first. Every known language is listed,
+/// even without votes, and ties keep the `languages` order.
+fn tally(languages: &[Language], votes: &[Vote], mode: TallyMode) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = languages.iter().map(|l| (l.name.clone(), 0)).collect();
+    for vote in votes {
+        let points = match mode {
+            TallyMode::Count => 1,
+            TallyMode::Weighted => vote.weight as usize,
+        };
+        match counts.iter_mut().find(|(name, _)| name == &vote.name) {
+            Some((_, count)) => *count += points,
+            None => counts.push((vote.name.clone(), points)),
+        }
+    }
+    counts.sort_by(|a, b| b.1.cmp(&a.1));
+    counts
+}
+
+/// Local votes count whether they are public or not, since they are this node's own choices.
+/// Remote votes are always public ones.
+#[tauri::command]
+fn tally_votes(
+    include_remote: bool,
+    mode: Option<TallyMode>,
+) -> std::result::Result<Vec<(String, usize)>, String> {
+    let mut votes = read_local_votes().map_err(|e| e.to_string())?;
+    if include_remote {
+        let remote_votes = read_remote_votes(&TOPIC_NAME).map_err(|e| e.to_string())?;
+        votes.extend(remote_votes.into_values().flatten());
+    }
+    let languages = known_languages().map_err(|e| e.to_string())?;
+    Ok(tally(&languages, &votes, mode.unwrap_or(TallyMode::Count)))
+}
+
+/// Most buckets `stats_over_time` returns, a tiny bucket over a long poll would be huge
+const MAX_BUCKETS: u64 = 10_000;
+
+/// Votes per `bucket_secs` wide bucket, as `(bucket start, count)` pairs. Buckets start at unix
+/// times multiple of `bucket_secs` and go from the earliest vote to the latest one, empty ones
+/// included. Votes stored before their creation time was recorded are left out, they would
+/// stretch the buckets back to 1970.
+fn histogram(votes: &[Vote], bucket_secs: u64) -> std::result::Result<Vec<(u64, usize)>, String> {
+    if bucket_secs == 0 {
+        return Err("bucket size must be positive".to_owned());
+    }
+    let votes: Vec<&Vote> = votes.iter().filter(|r| r.created_at != 0).collect();
+    let bucket_of = |vote: &&Vote| vote.created_at / 1000 / bucket_secs;
+    let (first, last) = match (
+        votes.iter().map(bucket_of).min(),
+        votes.iter().map(bucket_of).max(),
+    ) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Ok(vec![]),
+    };
+    if last - first >= MAX_BUCKETS {
+        return Err(format!(
+            "more than {} buckets, use larger ones",
+            MAX_BUCKETS
+        ));
+    }
+    let mut counts = vec![0; (last - first + 1) as usize];
+    for vote in &votes {
+        counts[(bucket_of(vote) - first) as usize] += 1;
+    }
+    Ok(counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, count)| ((first + i as u64) * bucket_secs, count))
+        .collect())
+}
+
+/// Voting momentum of the default poll. Like `tally_votes`, local votes count whether public or
+/// not, and remote votes are only included when asked for.
+#[tauri::command]
+fn stats_over_time(
+    bucket_secs: u64,
+    include_remote: bool,
+) -> std::result::Result<Vec<(u64, usize)>, String> {
+    let mut votes = read_local_votes().map_err(|e| e.to_string())?;
+    if include_remote {
+        let remote_votes = read_remote_votes(&TOPIC_NAME).map_err(|e| e.to_string())?;
+        votes.extend(remote_votes.into_values().flatten());
+    }
+    histogram(&votes, bucket_secs)
+}
+
+#[tauri::command]
+fn get_votes_sorted() -> std::result::Result<Votes, String> {
+    let mut votes = read_local_votes().map_err(|e| e.to_string())?;
+    votes.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(votes)
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+enum ExportFormat {
+    Json,
+    Csv,
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(|c: char| matches!(c, ',' | '"' | '\n' | '\r')) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+fn write_votes_csv(out: &mut impl Write, votes: &[Vote]) -> io::Result<()> {
+    writeln!(out, "id,name,public,created_at,comment,weight")?;
+    for vote in votes {
+        writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            vote.id,
+            csv_field(&vote.name),
+            vote.public,
+            vote.created_at,
+            csv_field(vote.comment.as_deref().unwrap_or("")),
+            vote.weight
+        )?;
+    }
+    Ok(())
+}
+
+/// A JSON array of `votes`, serialized one vote at a time
+fn write_votes_json(out: &mut impl Write, votes: &[Vote]) -> Result<()> {
+    out.write_all(b"[")?;
+    for (i, vote) in votes.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        out.write_all(b"\n")?;
+        serde_json::to_writer_pretty(&mut *out, vote)?;
+    }
+    out.write_all(b"\n]\n")?;
+    Ok(())
+}
+
+/// Writes the local votes to `path` one vote at a time. They are still read in full beforehand,
+/// the votes file being a single, possibly encrypted, document: memory use grows with the vote
+/// count, which `MAX_VOTES_ENV` bounds.
+fn export_votes_to(path: &Path, format: ExportFormat) -> Result<()> {
+    match path.parent() {
+        Some(parent) if parent.as_os_str().is_empty() || parent.is_dir() => (),
+        _ => return Err(format!("{} is not in an existing directory", path.display()).into()),
+    }
+    let votes = read_local_votes()?;
+    // written as it's serialized, so a large export doesn't need a second copy in memory
+    let mut out = io::BufWriter::new(fs::File::create(path)?);
+    match format {
+        ExportFormat::Json => write_votes_json(&mut out, &votes)?,
+        ExportFormat::Csv => write_votes_csv(&mut out, &votes)?,
+    }
+    out.flush()?;
+    info!("Exported {} votes to {}", votes.len(), path.display());
+    Ok(())
+}
+
+#[tauri::command]
+fn export_votes(path: String, format: ExportFormat) -> std::result::Result<(), String> {
+    export_votes_to(Path::new(&path), format).map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+enum ImportMode {
+    /// Overwrite the local votes with the imported ones
+    Replace,
+    /// Add imported votes for languages not voted for yet
+    Merge,
+}
+
+/// Imports an exported (or backed up) votes file, returning how many votes were added. Imported
+/// votes follow the same rules as added ones, a single invalid vote failing the whole import.
+fn import_votes_from(path: &Path, mode: ImportMode) -> Result<usize> {
+    // parse and check everything before touching the storage, so a bad file leaves it intact
+    let imported = parse_storage(&fs::read(path)?)?.votes;
+    for vote in &imported {
+        check_vote(&vote.name, vote.comment.as_deref(), vote.weight)?;
+    }
+    let _storage = lock_storage();
+    let added = match mode {
+        ImportMode::Replace => {
+            if imported.len() > max_votes() {
+                return Err(AddVoteError::LimitExceeded(max_votes()).into());
+            }
+            let mut names = HashSet::new();
+            if let Some(vote) = imported.iter().find(|r| !names.insert(r.name.as_str())) {
+                return Err(AddVoteError::Duplicate(vote.name.clone()).into());
+            }
+            let mut ids = HashSet::new();
+            if let Some(vote) = imported.iter().find(|r| !ids.insert(r.id)) {
+                return Err(AddVoteError::DuplicateId(vote.id).into());
+            }
+            // the imported votes keep their ids, so the counter carries on from theirs
+            write_poll_storage(
+                &TOPIC_NAME,
+                &Storage {
+                    version: STORAGE_VERSION,
+                    votes: imported.clone(),
+                    next_id: next_vote_id(&imported),
+                },
+            )?;
+            record_audit(AuditEvent::new(
+                AuditOperation::Reset,
+                &TOPIC_NAME,
+                None,
+                None,
+            ));
+            imported
+        }
+        ImportMode::Merge => {
+            let mut storage = read_poll_storage(&TOPIC_NAME)?;
+            let mut added = vec![];
+            for vote in imported {
+                if storage.votes.len() >= max_votes() {
+                    error!("votes limit of {} reached, stopping import", max_votes());
+                    break;
+                }
+                if storage.votes.iter().any(|r| r.name == vote.name) {
+                    continue;
+                }
+                let vote = Vote {
+                    id: storage.allocate_id(),
+                    ..vote
+                };
+                storage.votes.push(vote.clone());
+                added.push(vote);
+            }
+            write_poll_storage(&TOPIC_NAME, &storage)?;
+            added
+        }
+    };
+    for vote in &added {
+        record_audit(AuditEvent::new(
+            AuditOperation::Import,
+            &TOPIC_NAME,
+            Some(vote.id),
+            Some(&vote.name),
+        ));
+    }
+    info!("Imported {} votes from {}", added.len(), path.display());
+    Ok(added.len())
+}
+
+#[tauri::command]
+fn import_votes(path: String, mode: ImportMode, window: Window) -> tauri::Result<()> {
+    reject_in_observer_mode(&window, "import_votes")?;
+    import_votes_from(Path::new(&path), mode)
+        .map_err(|e| command_error(&window, "import_votes", e))?;
+
+    // once for the whole import, however many votes it added
+    emit_votes(&window, &TOPIC_NAME)?;
+
+    Ok(())
+}
+
+/// Diagnostics returned by the `health` command
+#[derive(Debug, Serialize)]
+struct HealthReport {
+    peer_id: String,
+    discovered_peers: usize,
+    connected_peers: usize,
+    local_votes: usize,
+    public_votes: usize,
+    storage_path: String,
+    /// `false` when the votes file can't be read or parsed, a missing file is fine
+    storage_readable: bool,
+}
+
+/// Votes of the default poll, read without moving a corrupt file aside like `read_local_votes`
+fn inspect_storage(path: &str) -> Option<Votes> {
+    match fs::read(path) {
+        Ok(content) => parse_storage(&content).ok().map(|storage| storage.votes),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Some(vec![]),
+        Err(_) => None,
+    }
+}
+
+#[tauri::command]
+fn health(
+    peers_state: State<PeersState>,
+    connections_state: State<ConnectionsState>,
+) -> HealthReport {
+    let (storage_path, votes) = match poll_storage_file_path(&TOPIC_NAME) {
+        Ok(path) => {
+            let votes = inspect_storage(&path);
+            (path, votes)
+        }
+        Err(_) => (String::new(), None),
+    };
+    HealthReport {
+        peer_id: local_peer_id().to_string(),
+        discovered_peers: peers_state
+            .peers
+            .lock()
+            .expect("peers lock is poisoned")
+            .len(),
+        connected_peers: connections_state
+            .peers
+            .lock()
+            .expect("connections lock is poisoned")
+            .len(),
+        local_votes: votes.as_ref().map_or(0, Vec::len),
+        public_votes: votes
+            .as_ref()
+            .map_or(0, |votes| votes.iter().filter(|v| v.public).count()),
+        storage_path,
+        storage_readable: votes.is_some(),
+    }
+}
+
+#[tauri::command]
+fn read_audit(limit: usize) -> std::result::Result<Vec<AuditEvent>, String> {
+    read_audit_entries(limit).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_metrics(state: State<MetricsState>) -> Metrics {
+    state.counters.snapshot()
+}
+
+/// Clears the addresses remembered for redialing at startup
+#[tauri::command]
+fn forget_peers() -> std::result::Result<(), String> {
+    match fs::remove_file(get_data_file_path(PEERS_FILE_NAME).map_err(|e| e.to_string())?) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.to_string()),
+        _ => Ok(()),
+    }
+}
+
+#[tauri::command]
+fn is_local_only() -> bool {
+    *LOCAL_ONLY
+}
+
+#[tauri::command]
+fn is_dry_run() -> bool {
+    *DRY_RUN
+}
+
+/// Lets the frontend hide voting controls on observer nodes
+#[tauri::command]
+fn is_observer() -> bool {
+    *OBSERVER
+}
+
+#[tauri::command]
+fn get_peer_id() -> String {
+    local_peer_id().to_string()
+}
+
+/// Replaces the node key with a fresh one, then restarts the swarm under the new peer id and
+/// emits `identity_changed` with it. Votes are kept, only the identity peers see changes.
+#[tauri::command]
+async fn regenerate_identity(
+    window: Window,
+    state: State<'_, SwarmState>,
+) -> tauri::Result<String> {
+    let peer_id = state
+        .request(|reply| SwarmCommand::RegenerateIdentity { reply })
+        .await
+        .and_then(|result| result)
+        .map_err(|e| command_error(&window, "regenerate_identity", e))?;
+    window.emit("identity_changed", json!({ "peer_id": peer_id }))?;
+    Ok(peer_id)
+}
+
+#[tauri::command]
+fn get_listen_addrs(state: State<ListenAddrsState>) -> Vec<String> {
+    state
+        .addrs
+        .lock()
+        .expect("listen addrs lock is poisoned")
+        .iter()
+        .map(|a| a.to_string())
+        .collect()
+}
+
+/// Prefix of connection strings, so other QR codes are told apart
+const CONNECTION_STRING_PREFIX: &str = "vote:";
+
+/// The listen address other devices are most likely to reach, non-loopback IPv4 first
+fn best_dialable_addr(addrs: &[Multiaddr]) -> Option<Multiaddr> {
+    let is_loopback = |a: &Multiaddr| {
+        a.iter().any(|p| match p {
+            Protocol::Ip4(ip) => ip.is_loopback() || ip.is_unspecified(),
+            Protocol::Ip6(ip) => ip.is_loopback() || ip.is_unspecified(),
+            _ => false,
+        })
+    };
+    let is_ip4 = |a: &Multiaddr| a.iter().any(|p| matches!(p, Protocol::Ip4(_)));
+    addrs
+        .iter()
+        .filter(|a| !is_loopback(a))
+        .max_by_key(|a| is_ip4(a))
+        .or_else(|| addrs.first())
+        .map(dialable_addr)
+}
+
+/// `vote:` followed by the base58 of the binary multiaddr, short enough for a QR code
+fn encode_connection_string(address: &Multiaddr) -> String {
+    format!(
+        "{}{}",
+        CONNECTION_STRING_PREFIX,
+        bs58::encode(address.to_vec()).into_string()
+    )
+}
+
+fn decode_connection_string(s: &str) -> std::result::Result<Multiaddr, String> {
+    let encoded = s
+        .trim()
+        .strip_prefix(CONNECTION_STRING_PREFIX)
+        .ok_or_else(|| format!("not a connection string: {}", s))?;
+    let bytes = bs58::decode(encoded)
+        .into_vec()
+        .map_err(|e| format!("invalid connection string: {}", e))?;
+    let address =
+        Multiaddr::try_from(bytes).map_err(|e| format!("invalid connection string: {}", e))?;
+    match address.iter().last() {
+        Some(Protocol::P2p(multihash)) if PeerId::from_multihash(multihash).is_ok() => Ok(address),
+        _ => Err(format!("connection string without a peer id: {}", address)),
+    }
+}
+
+#[tauri::command]
+fn get_connection_string(state: State<ListenAddrsState>) -> std::result::Result<String, String> {
+    let addrs = state.addrs.lock().expect("listen addrs lock is poisoned");
+    best_dialable_addr(&addrs)
+        .map(|address| encode_connection_string(&address))
+        .ok_or_else(|| "not listening on any address yet".to_owned())
+}
+
+#[tauri::command]
+async fn connect_via_string(
+    s: String,
+    state: State<'_, SwarmState>,
+) -> std::result::Result<(), String> {
+    let address = decode_connection_string(&s)?;
+    state
+        .request(|reply| SwarmCommand::Dial { address, reply })
+        .await?
+}
+
+/// Largest page `get_votes_page` returns
+const MAX_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Serialize)]
+struct VotesPage {
+    items: Votes,
+    total: usize,
+}
+
+fn votes_page(votes: Votes, offset: usize, limit: usize) -> VotesPage {
+    let total = votes.len();
+    let items = votes
+        .into_iter()
+        .skip(offset)
+        .take(limit.min(MAX_PAGE_SIZE))
+        .collect();
+    VotesPage { items, total }
+}
+
+/// Lets the frontend load large vote sets lazily, unlike the full `get_votes` event
+#[tauri::command]
+fn get_votes_page(offset: usize, limit: usize) -> std::result::Result<VotesPage, String> {
+    let votes = read_local_votes().map_err(|e| e.to_string())?;
+    Ok(votes_page(votes, offset, limit))
+}
+
+/// Clears the local votes, keeping the previous file as `<votes file>.bak`
+fn reset_local_votes() -> Result<()> {
+    let _storage = lock_storage();
+    let path = get_storage_file_path()?;
+    if Path::new(&path).exists() {
+        fs::copy(&path, format!("{}.bak", path))?;
+    }
+    write_local_votes(&vec![])?;
+    record_audit(AuditEvent::new(
+        AuditOperation::Reset,
+        &TOPIC_NAME,
+        None,
+        None,
+    ));
+    info!("Reset local votes, previous ones saved to {}.bak", path);
+    Ok(())
+}
+
+/// Languages that can be voted for, fetched by the frontend when it mounts
+#[tauri::command]
+fn get_languages() -> std::result::Result<Vec<Language>, String> {
+    known_languages().map_err(|e| e.to_string())
+}
+
+fn emit_languages(window: &Window) -> tauri::Result<()> {
+    let languages = known_languages().map_err(|e| command_error(window, "read_languages", e))?;
+    window.emit(
+        "get_languages",
+        json!({
+            "languages": languages,
+        }),
+    )
+}
+
+#[tauri::command]
+fn add_language(name: String, window: Window) -> tauri::Result<()> {
+    add_custom_language(&name).map_err(|e| command_error(&window, "add_language", e))?;
+    emit_languages(&window)
+}
+
+#[tauri::command]
+fn remove_language(name: String, window: Window) -> tauri::Result<()> {
+    remove_custom_language(&name).map_err(|e| command_error(&window, "remove_language", e))?;
+    emit_languages(&window)
+}
+
+#[tauri::command]
+fn set_vote_visibility(
+    id: usize,
+    public: bool,
+    poll: Option<String>,
+    window: Window,
+) -> tauri::Result<()> {
+    reject_in_observer_mode(&window, "set_vote_visibility")?;
+    let poll = poll_or_default(poll);
+    set_visibility(&poll, id, public)
+        .map_err(|e| command_error(&window, "set_vote_visibility", e))?;
+
+    emit_votes(&window, &poll)?;
+
+    Ok(())
+}
+
+/// Announces every public vote of the poll to all peers, e.g. after reconnecting, returning how
+/// many were sent. Peers missing some don't have to ask for them.
+#[tauri::command]
+async fn rebroadcast(
+    poll: Option<String>,
+    window: Window,
+    state: State<'_, SwarmState>,
+    rebroadcasts: State<'_, RebroadcastState>,
+) -> tauri::Result<usize> {
+    reject_in_observer_mode(&window, "rebroadcast")?;
+    let poll = poll_or_default(poll);
+    let previous = rebroadcasts.reserve(Instant::now()).map_err(|elapsed| {
+        command_error(
+            &window,
+            "rebroadcast",
+            format!(
+                "votes were broadcast {}s ago, wait {}s",
+                elapsed.as_secs(),
+                (REBROADCAST_INTERVAL - elapsed).as_secs() + 1
+            ),
+        )
+    })?;
+    let count = state
+        .request(|reply| SwarmCommand::Rebroadcast {
+            poll: poll.clone(),
+            reply,
+        })
+        .await
+        .and_then(|result| result)
+        .map_err(|e| {
+            rebroadcasts.release(previous);
+            command_error(&window, "rebroadcast", e)
+        })?;
+
+    info!("Rebroadcast {} public votes of {}", count, poll);
+    Ok(count)
+}
+
+/// Publishes several votes at once, with a single write and a single broadcast. Returns the ids
+/// that were actually made public.
+#[tauri::command]
+fn publish_votes(
+    ids: Vec<usize>,
+    poll: Option<String>,
+    window: Window,
+    state: State<SenderState>,
+) -> tauri::Result<Vec<usize>> {
+    reject_in_observer_mode(&window, "publish_votes")?;
+    let poll = poll_or_default(poll);
+    let published =
+        publish_local_votes(&poll, &ids).map_err(|e| command_error(&window, "publish_votes", e))?;
+    if published.is_empty() {
+        return Ok(published);
+    }
+
+    let cloned_state = state.sender.clone();
+    let cloned_poll = poll.clone();
+
+    tauri::async_runtime::spawn(async move {
+        respond_with_public_votes(
+            cloned_state,
+            cloned_poll,
+            BROADCAST_RECEIVER.to_owned(),
+            ListMode::ALL,
+            ResponseTarget::Topic,
+            None,
+        );
+    });
+
+    emit_votes(&window, &poll)?;
+
+    Ok(published)
+}
+
+/// `expires_at` is a unix time in milliseconds, `None` keeping the vote forever
+#[tauri::command]
+fn set_vote_expiry(
+    id: usize,
+    expires_at: Option<u64>,
+    poll: Option<String>,
+    window: Window,
+) -> tauri::Result<()> {
+    reject_in_observer_mode(&window, "set_vote_expiry")?;
+    let poll = poll_or_default(poll);
+    set_expiry(&poll, id, expires_at).map_err(|e| command_error(&window, "set_vote_expiry", e))?;
+
+    emit_votes(&window, &poll)?;
+
+    Ok(())
+}
+
+#[tauri::command]
+fn rename_vote(
+    id: usize,
+    new_name: String,
+    poll: Option<String>,
+    window: Window,
+) -> tauri::Result<()> {
+    reject_in_observer_mode(&window, "rename_vote")?;
+    let poll = poll_or_default(poll);
+    rename_local_vote(&poll, id, &new_name)
+        .map_err(|e| command_error(&window, "rename_vote", e))?;
+
+    emit_votes(&window, &poll)?;
+
+    Ok(())
+}
+
+/// Useful when a language is retired from a poll, not finding any vote isn't an error
+#[tauri::command]
+fn delete_votes_by_language(
+    name: String,
+    poll: Option<String>,
+    window: Window,
+) -> tauri::Result<usize> {
+    reject_in_observer_mode(&window, "delete_votes_by_language")?;
+    let poll = poll_or_default(poll);
+    let removed = delete_votes_named(&poll, &name)
+        .map_err(|e| command_error(&window, "delete_votes_by_language", e))?;
+
+    emit_votes(&window, &poll)?;
+
+    Ok(removed)
+}
+
+#[tauri::command]
+fn restore_backup(index: usize, poll: Option<String>, window: Window) -> tauri::Result<()> {
+    reject_in_observer_mode(&window, "restore_backup")?;
+    let poll = poll_or_default(poll);
+    restore_poll_backup(&poll, index).map_err(|e| command_error(&window, "restore_backup", e))?;
+
+    emit_votes(&window, &poll)?;
+
+    Ok(())
+}
+
+#[tauri::command]
+fn reset_votes(window: Window) -> tauri::Result<()> {
+    reject_in_observer_mode(&window, "reset_votes")?;
+    reset_local_votes().map_err(|e| command_error(&window, "reset_votes", e))?;
+
+    emit_votes(&window, &TOPIC_NAME)?;
+
+    Ok(())
+}
+
+/// Emits `votes_changed` with a `VotesUpdate` every time local or remote votes are written,
+/// until the window goes away. Meant to be called once, when the frontend mounts.
+#[tauri::command]
+fn watch_votes(window: Window) {
+    let mut updates = VOTES_WATCH.subscribe();
+    tauri::async_runtime::spawn(async move {
+        while updates.changed().await.is_ok() {
+            let update = updates.borrow().clone();
+            if let Some(update) = update {
+                if let Err(e) = window.emit("votes_changed", &update) {
+                    info!("Stopping votes watch, {}", e);
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// Votes cast by this node, unlike `get_remote_votes` which returns the ones learned from peers
+#[tauri::command]
+fn get_local_votes(poll: Option<String>) -> std::result::Result<Votes, String> {
+    read_poll_votes(&poll_or_default(poll)).map_err(|e| e.to_string())
+}
+
+/// Public votes learned from peers, keyed by source peer id
+#[tauri::command]
+fn get_remote_votes(poll: Option<String>) -> std::result::Result<RemoteVotes, String> {
+    read_remote_votes(&poll_or_default(poll)).map_err(|e| e.to_string())
+}
+
+/// Asks a single peer for its public votes, failing when it doesn't answer before the query timeout
+#[tauri::command]
+async fn fetch_peer_votes(
+    peer_id: String,
+    poll: Option<String>,
+    state: State<'_, SwarmState>,
+) -> std::result::Result<Votes, String> {
+    let peer_id = parse_peer_id(&peer_id)?;
+    let poll = poll_or_default(poll);
+    state
+        .request(|reply| SwarmCommand::FetchPeerVotes {
+            peer_id,
+            poll,
+            reply,
+        })
+        .await?
+}
+
+/// Stops an `ls v` query or a `fetch_peer_votes` call, its late responses being dropped.
+/// Returns `false` when the query already completed or never existed.
+#[tauri::command]
+async fn cancel_query(
+    request_id: String,
+    state: State<'_, SwarmState>,
+) -> std::result::Result<bool, String> {
+    state
+        .request(|reply| SwarmCommand::CancelQuery { request_id, reply })
+        .await
+}
+
+/// Peers subscribed to the poll, so the user knows whether their vote will reach anyone
+#[tauri::command]
+async fn subscribed_peers(
+    poll: Option<String>,
+    state: State<'_, SwarmState>,
+) -> std::result::Result<Vec<String>, String> {
+    let poll = poll_or_default(poll);
+    state
+        .request(|reply| SwarmCommand::SubscribedPeers { poll, reply })
+        .await
+}
+
+#[tauri::command]
+async fn join_poll(topic: String, state: State<'_, SwarmState>) -> std::result::Result<(), String> {
+    state
+        .request(|reply| SwarmCommand::JoinPoll { topic, reply })
+        .await?
+}
+
+/// Stops taking part in the default poll, until `go_online`
+#[tauri::command]
+async fn go_offline(state: State<'_, SwarmState>) -> std::result::Result<(), String> {
+    state
+        .request(|reply| SwarmCommand::SetOnline {
+            online: false,
+            reply,
+        })
+        .await?
+}
+
+#[tauri::command]
+async fn go_online(state: State<'_, SwarmState>) -> std::result::Result<(), String> {
+    state
+        .request(|reply| SwarmCommand::SetOnline {
+            online: true,
+            reply,
+        })
+        .await?
+}
+
+#[tauri::command]
+async fn leave_poll(
+    topic: String,
+    state: State<'_, SwarmState>,
+) -> std::result::Result<(), String> {
+    state
+        .request(|reply| SwarmCommand::LeavePoll { topic, reply })
+        .await?
+}
+
+/// Last known round-trip time to each peer, in milliseconds
+#[tauri::command]
+fn peer_latencies(state: State<LatenciesState>) -> HashMap<String, u64> {
+    state
+        .latencies
+        .lock()
+        .expect("latencies lock is poisoned")
+        .iter()
+        .map(|(peer, rtt)| (peer.to_string(), rtt.as_millis() as u64))
+        .collect()
+}
+
+/// Peers that sent a heartbeat recently, most recent first. Also emitted as `online_peers`
+/// whenever a peer comes online or goes offline.
+#[tauri::command]
+fn online_peers(state: State<PresenceState>) -> Vec<PeerInfo> {
+    state
+        .roster
+        .lock()
+        .expect("roster lock is poisoned")
+        .online(now_millis())
+}
+
+/// Name to show for every peer known to this node, discovered, connected or online. Peers
+/// that never sent a display name get a shortened peer id.
+#[tauri::command]
+fn peer_labels(
+    presence_state: State<PresenceState>,
+    peers_state: State<PeersState>,
+    connections_state: State<ConnectionsState>,
+) -> HashMap<String, String> {
+    let roster = presence_state
+        .roster
+        .lock()
+        .expect("roster lock is poisoned");
+    let mut peers: HashSet<PeerId> = roster.peers().copied().collect();
+    peers.extend(
+        peers_state
+            .peers
+            .lock()
+            .expect("peers lock is poisoned")
+            .iter(),
+    );
+    peers.extend(
+        connections_state
+            .peers
+            .lock()
+            .expect("connections lock is poisoned")
+            .iter(),
+    );
+    peers
+        .iter()
+        .map(|peer| (peer.to_string(), roster.label(peer)))
+        .collect()
+}
+
+#[tauri::command]
+fn list_peers(peers_state: State<PeersState>) -> Vec<String> {
+    list_known_peers(&peers_state.peers)
+}
+
+fn publish_response(
+    swarm: &mut Swarm<VoteBehaviour>,
+    poll: &str,
+    payload: &[u8],
+    target: ResponseTarget,
+) -> std::result::Result<(), String> {
+    match target {
+        ResponseTarget::Topic => info!("Publishing response to {}", poll),
+        // gossipsub can't address a single peer: until a direct protocol exists, targeted
+        // replies still go through the topic and other peers drop them based on `receiver`
+        ResponseTarget::Peer(peer) => info!("Publishing response for {}", peer),
+    }
+    // peers would drop it unread
+    if payload.len() > MAX_MESSAGE_BYTES {
+        return Err(format!(
+            "response of {} bytes is over the {} bytes limit",
+            payload.len(),
+            MAX_MESSAGE_BYTES
+        ));
+    }
+    let behaviour = swarm.behaviour_mut();
+    let sent = &behaviour.counters.responses_sent;
+    publish_counted(&mut behaviour.gossipsub, sent, poll, payload, *DRY_RUN)
+        .map_err(|e| format!("error publishing response: {}", e))
+}
+
+/// Shows `response` to the frontend as `new`, then publishes it on `poll`
+fn send_response(
+    swarm: &mut Swarm<VoteBehaviour>,
+    window: &Window,
+    poll: &str,
+    response: &ListResponse,
+    target: ResponseTarget,
+) -> std::result::Result<(), String> {
+    let json = serde_json::to_string(response).expect("cannot jsonify response");
+    info!(
+        "Sending {} votes to {} on {}",
+        response.data.len(),
+        response.receiver,
+        poll
+    );
+    debug!("Response payload {}", json);
+    if let Err(e) = window.emit("new", &json) {
+        error!("error emitting new event, {}", e);
+    }
+    let payload = WIRE_FORMAT
+        .encode(response)
+        .expect("cannot encode response");
+    publish_response(swarm, poll, &payload, target)
+}
+
+/// Unsolicited response carrying `data` to everyone, unique even when the votes didn't change
+fn broadcast_response(data: Votes) -> ListResponse {
+    ListResponse {
+        mode: ListMode::ALL,
+        data,
+        receiver: BROADCAST_RECEIVER.to_owned(),
+        request_id: None,
+        nonce: Some(rand::random()),
+    }
+}
+
+/// Publishes every public vote of `poll` on it, returning how many were sent
+fn rebroadcast_votes(
+    swarm: &mut Swarm<VoteBehaviour>,
+    window: &Window,
+    poll: &str,
+) -> std::result::Result<usize, String> {
+    let votes = read_poll_votes(poll).map_err(|e| e.to_string())?;
+    let data = shareable_votes(&node_keys(), votes).map_err(|e| e.to_string())?;
+    let count = data.len();
+    send_response(
+        swarm,
+        window,
+        poll,
+        &broadcast_response(data),
+        ResponseTarget::Topic,
+    )?;
+    Ok(count)
+}
+
+fn join_or_leave_poll(
+    gossipsub: &mut Gossipsub,
+    topic: &str,
+    join: bool,
+) -> std::result::Result<(), String> {
+    let poll = IdentTopic::new(topic);
+    if join {
+        gossipsub.subscribe(&poll).map_err(|e| format!("{:?}", e))?;
+        info!("Joined poll {}", topic);
+    } else {
+        gossipsub
+            .unsubscribe(&poll)
+            .map_err(|e| format!("{:?}", e))?;
+        info!("Left poll {}", topic);
+    }
+    Ok(())
+}
+
+/// Leaves or rejoins the default topic without quitting, emitting `subscription_changed`
+fn set_online(
+    swarm: &mut Swarm<VoteBehaviour>,
+    window: &Window,
+    online: bool,
+) -> std::result::Result<(), String> {
+    let behaviour = swarm.behaviour_mut();
+    join_or_leave_poll(&mut behaviour.gossipsub, &TOPIC_NAME, online)?;
+    behaviour.offline = !online;
+    info!("Now {}", if online { "online" } else { "offline" });
+    if let Err(e) = window.emit("subscription_changed", json!({ "online": online })) {
+        error!("error emitting subscription_changed event, {}", e);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn dial_peer(
+    address: String,
+    state: State<'_, SwarmState>,
+) -> std::result::Result<(), String> {
+    let address = address
+        .parse::<Multiaddr>()
+        .map_err(|e| format!("invalid address {}: {}", address, e))?;
+    for protocol in address.iter() {
+        if let Protocol::P2p(multihash) = protocol {
+            PeerId::from_multihash(multihash)
+                .map_err(|_| format!("invalid peer id in address {}", address))?;
+        }
+    }
+    state
+        .request(|reply| SwarmCommand::Dial { address, reply })
+        .await?
+}
+
+fn emit_dial_error(window: &Window, address: &str, message: String) {
+    error!("error dialing {}: {}", address, message);
+    if let Err(e) = window.emit(
+        "dial_error",
+        json!({
+            "address": address,
+            "message": message,
+        }),
+    ) {
+        error!("error emitting dial_error event, {}", e);
+    }
+}
+
+/// Starts dialing `address`, emitting `dial_error` when it can't be
+fn dial(
+    swarm: &mut Swarm<VoteBehaviour>,
+    window: &Window,
+    address: Multiaddr,
+) -> std::result::Result<(), String> {
+    info!("Dialing {}", address);
+    swarm.dial_addr(address.clone()).map_err(|e| {
+        emit_dial_error(window, &address.to_string(), e.to_string());
+        e.to_string()
+    })
+}
+
+/// Per-peer file of recently discovered peer addresses, redialed at startup
+const PEERS_FILE_NAME: &str = "peers.json";
+/// How many addresses `peers.json` keeps
+const MAX_STORED_PEERS: usize = 100;
+/// How many of the most recent addresses are redialed at startup
+const STARTUP_REDIALS: usize = 10;
+/// Addresses not seen for longer than this many seconds are forgotten, a week by default
+const PEER_MAX_AGE_ENV: &str = "VOTINGDAPP_PEER_MAX_AGE_SECS";
+const DEFAULT_PEER_MAX_AGE_SECS: u64 = 7 * 24 * 60 * 60;
+
+fn read_peer_store() -> Result<PeerStore> {
+    match fs::read(get_data_file_path(PEERS_FILE_NAME)?) {
+        Ok(content) => Ok(serde_json::from_slice(&content)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(PeerStore::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_peer_store(store: &PeerStore) -> Result<()> {
+    let json = serde_json::to_string(store)?;
+    fs::write(get_data_file_path(PEERS_FILE_NAME)?, &json)?;
+    Ok(())
+}
+
+fn remember_peers(discovered: &[(PeerId, Multiaddr)]) {
+    let now = now_millis() / 1000;
+    let result = read_peer_store().and_then(|mut store| {
+        for (peer, addr) in discovered {
+            store.record(peer, addr, now, MAX_STORED_PEERS);
+        }
+        store.prune(env_or(PEER_MAX_AGE_ENV, DEFAULT_PEER_MAX_AGE_SECS), now);
+        write_peer_store(&store)
+    });
+    if let Err(e) = result {
+        error!("error storing discovered peers: {}", e);
+    }
+}
+
+/// Redials the peers seen most recently, so they are found before mDNS rediscovers them
+fn dial_known_peers(swarm: &mut Swarm<VoteBehaviour>, window: &Window) {
+    let mut store = match read_peer_store() {
+        Ok(store) => store,
+        Err(e) => return error!("error reading known peers: {}", e),
+    };
+    store.prune(
+        env_or(PEER_MAX_AGE_ENV, DEFAULT_PEER_MAX_AGE_SECS),
+        now_millis() / 1000,
+    );
+    for address in store.most_recent(STARTUP_REDIALS) {
+        let _ = dial(swarm, window, address);
+    }
+}
+
+/// Dials the peers of `VOTINGDAPP_BOOTSTRAP`, returning their addresses
+fn dial_bootstrap_peers(swarm: &mut Swarm<VoteBehaviour>, window: &Window) -> Vec<Multiaddr> {
+    let addresses = match env::var(BOOTSTRAP_ENV) {
+        Ok(addresses) => addresses,
+        Err(_e) => return vec![],
+    };
+    let mut dialed = vec![];
+    for address in addresses
+        .split(',')
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+    {
+        match address.parse::<Multiaddr>() {
+            Ok(address) => {
+                let _ = dial(swarm, window, address.clone());
+                dialed.push(address);
+            }
+            Err(e) => emit_dial_error(window, address, e.to_string()),
+        }
+    }
+    dialed
+}
+
+fn schedule_reconnect(window: &Window, reconnects: &mut Reconnects, address: &Multiaddr) {
+    if let Some((attempt, delay)) = reconnects.disconnected(address, Instant::now()) {
+        info!("Redialing {} in {:?}", address, delay);
+        if let Err(e) = window.emit(
+            "reconnecting",
+            json!({
+                "address": address.to_string(),
+                "attempt": attempt,
+                "delay_secs": delay.as_secs(),
+            }),
+        ) {
+            error!("error emitting reconnecting event, {}", e);
+        }
+    }
+}
+
+fn redial_due(swarm: &mut Swarm<VoteBehaviour>, window: &Window, reconnects: &mut Reconnects) {
+    for address in reconnects.due(Instant::now()) {
+        let _ = dial(swarm, window, address);
+    }
+}
+
+/// Applies `change` to the connected peers, emitting `connection_status` if it did anything
+fn update_connections(window: &Window, change: impl FnOnce(&mut HashSet<PeerId>) -> bool) {
+    let state = window.state::<ConnectionsState>();
+    let mut peers = state.peers.lock().expect("connections lock is poisoned");
+    if change(&mut peers) {
+        if let Err(e) = window.emit("connection_status", json!({ "connected": peers.len() })) {
+            error!("error emitting connection_status event, {}", e);
+        }
+    }
+}
+
+/// A listen address completed with our peer id, so other nodes can dial it as is
+fn dialable_addr(address: &Multiaddr) -> Multiaddr {
+    address.clone().with(Protocol::P2p(local_peer_id().into()))
+}
+
+fn handle_swarm_event<E: std::fmt::Debug>(
+    window: &Window,
+    reconnects: &mut Reconnects,
+    event: SwarmEvent<(), E>,
+) {
+    match event {
+        SwarmEvent::NewListenAddr { address, .. } => {
+            let dialable = dialable_addr(&address);
+            info!("Listening on {}", dialable);
+            if let Err(e) = window.emit("listen_addr", json!({ "address": dialable.to_string() })) {
+                error!("error emitting listen_addr event, {}", e);
+            }
+            let state = window.state::<ListenAddrsState>();
+            let mut addrs = state.addrs.lock().expect("listen addrs lock is poisoned");
+            if !addrs.contains(&address) {
+                addrs.push(address);
+            }
+        }
+        SwarmEvent::ExpiredListenAddr { address, .. } => {
+            info!("No longer listening on {}", address);
+            let state = window.state::<ListenAddrsState>();
+            let mut addrs = state.addrs.lock().expect("listen addrs lock is poisoned");
+            addrs.retain(|a| a != &address);
+        }
+        // a peer can have several connections at once (e.g. both dialing each other),
+        // it only counts as disconnected once the last one is closed
+        SwarmEvent::ConnectionEstablished {
+            peer_id, endpoint, ..
+        } => {
+            info!("Connected to {}", peer_id);
+            if let ConnectedPoint::Dialer { address } = &endpoint {
+                reconnects.connected(address);
+            }
+            update_connections(window, |peers| peers.insert(peer_id));
+        }
+        SwarmEvent::ConnectionClosed {
+            peer_id,
+            endpoint,
+            num_established,
+            ..
+        } => {
+            if let ConnectedPoint::Dialer { address } = &endpoint {
+                schedule_reconnect(window, reconnects, address);
+            }
+            if num_established == 0 {
+                info!("Disconnected from {}", peer_id);
+                update_connections(window, |peers| peers.remove(&peer_id));
+            }
+        }
+        SwarmEvent::UnreachableAddr { address, error, .. }
+        | SwarmEvent::UnknownPeerUnreachableAddr { address, error } => {
+            emit_dial_error(window, &address.to_string(), error.to_string());
+            schedule_reconnect(window, reconnects, &address);
+        }
+        event => info!("Unhandled Swarm Event: {:?}", event),
+    }
+}
+
+/// Addresses to listen on, one per IP version for TCP
+fn listen_addrs(kind: TransportKind, families: IpFamilies, port: u16) -> Vec<Multiaddr> {
+    let (v4, v6) = match *LOCAL_ONLY {
+        true => ("127.0.0.1", "::1"),
+        false => ("0.0.0.0", "::"),
+    };
+    let addrs = match (kind, families) {
+        (TransportKind::Memory, _) => vec![format!("/memory/{}", port)],
+        (TransportKind::Tcp, IpFamilies::V4) => vec![format!("/ip4/{}/tcp/{}", v4, port)],
+        (TransportKind::Tcp, IpFamilies::V6) => vec![format!("/ip6/{}/tcp/{}", v6, port)],
+        (TransportKind::Tcp, IpFamilies::Dual) => vec![
+            format!("/ip4/{}/tcp/{}", v4, port),
+            format!("/ip6/{}/tcp/{}", v6, port),
+        ],
+    };
+    addrs
+        .iter()
+        .map(|a| a.parse().expect("can't get a local socket"))
+        .collect()
+}
+
+/// Listens on `address`, falling back to a random port when its own is already taken
+fn listen_on(
+    swarm: &mut Swarm<VoteBehaviour>,
+    window: &Window,
+    address: Multiaddr,
+) -> std::result::Result<(), String> {
+    let fixed_port = address.iter().any(|p| match p {
+        Protocol::Tcp(port) => port != 0,
+        Protocol::Memory(port) => port != 0,
+        _ => false,
+    });
+    match Swarm::listen_on(swarm, address.clone()) {
+        Ok(_) => Ok(()),
+        Err(TransportError::Other(e)) if fixed_port && e.kind() == io::ErrorKind::AddrInUse => {
+            emit_error(
+                window,
+                "listen",
+                &format!("{} is already in use, using a random port instead", address),
+            );
+            let random: Multiaddr = address
+                .iter()
+                .map(|p| match p {
+                    Protocol::Tcp(_) => Protocol::Tcp(0),
+                    Protocol::Memory(_) => Protocol::Memory(0),
+                    p => p,
+                })
+                .collect();
+            Swarm::listen_on(swarm, random)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Listens on every address of `families`. A machine without IPv6 still starts in dual mode,
+/// only failing to listen on all of them is fatal.
+fn listen(
+    swarm: &mut Swarm<VoteBehaviour>,
+    window: &Window,
+    kind: TransportKind,
+    families: IpFamilies,
+    port: u16,
+) {
+    let mut listening = false;
+    for address in listen_addrs(kind, families, port) {
+        match listen_on(swarm, window, address.clone()) {
+            Ok(()) => listening = true,
+            Err(e) => emit_error(
+                window,
+                "listen",
+                &format!("cannot listen on {}: {}", address, e),
+            ),
+        }
+    }
+    if !listening {
+        panic!("swarm can't be started, no address could be listened on");
+    }
+}
+
+/// Noise handshake used to secure connections, `xx` (default) or `ix`
+const HANDSHAKE_ENV: &str = "VOTINGDAPP_NOISE_HANDSHAKE";
+
+/// IK exists as well but needs the remote static key upfront, so it isn't offered
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum HandshakePattern {
+    /// Only XX currently provides interop with other libp2p impls
+    XX,
+    IX,
+}
+
+impl FromStr for HandshakePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "xx" => Ok(HandshakePattern::XX),
+            "ix" => Ok(HandshakePattern::IX),
+            _ => Err(format!("unknown handshake pattern: {}", s)),
+        }
+    }
+}
+
+fn secure<T>(
+    transport: T,
+    auth_keys: AuthenticKeypair<X25519Spec>,
+    pattern: HandshakePattern,
+) -> Boxed<(PeerId, StreamMuxerBox)>
+where
+    T: Transport + Clone + Send + Sync + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Dial: Send + 'static,
+    T::Listener: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+{
+    let upgraded = transport.upgrade(upgrade::Version::V1);
+    match pattern {
+        HandshakePattern::XX => upgraded
+            .authenticate(NoiseConfig::xx(auth_keys).into_authenticated())
+            .multiplex(mplex::MplexConfig::new())
+            .boxed(),
+        HandshakePattern::IX => upgraded
+            .authenticate(NoiseConfig::ix(auth_keys).into_authenticated())
+            .multiplex(mplex::MplexConfig::new())
+            .boxed(),
+    }
+}
+
+/// Noise-authenticated, mplex-multiplexed transport, over TCP or in memory
+fn build_transport(
+    keys: &identity::Keypair,
+    kind: TransportKind,
+    pattern: HandshakePattern,
+) -> Boxed<(PeerId, StreamMuxerBox)> {
+    let auth_keys = Keypair::<X25519Spec>::new()
+        .into_authentic(keys)
+        .expect("can't create auth keys");
+
+    match kind {
+        TransportKind::Tcp => secure(TokioTcpConfig::new(), auth_keys, pattern),
+        TransportKind::Memory => secure(MemoryTransport::default(), auth_keys, pattern),
+    }
+}
+
+/// Why `run_swarm` returned: the node key changed and the swarm has to be rebuilt with it
+struct IdentityChanged {
+    /// Polls the old swarm was subscribed to, joined again by the new one
+    polls: Vec<String>,
+    reply: oneshot::Sender<std::result::Result<String, String>>,
+}
+
+async fn initialize(window: &Window) {
+    info!("Topic: {}", *TOPIC);
+    let (response_sender, mut response_rcv) = mpsc::unbounded_channel();
+
+    window.manage(SenderState {
+        sender: response_sender.clone(),
+    });
+
+    let (command_sender, mut command_rcv) = mpsc::unbounded_channel();
+    tokio::spawn(read_stdin_commands(command_sender.clone()));
+    window.manage(SwarmState {
+        commands: command_sender,
+    });
+
+    let mut polls = vec![TOPIC_NAME.clone()];
+    let mut pending_reply = None;
+    loop {
+        let (swarm, reconnects) = start_swarm(window, response_sender.clone(), &polls).await;
+        if let Some(reply) = pending_reply.take() {
+            // the caller may have gone away, nothing to do then
+            let _ = reply.send(Ok(local_peer_id().to_string()));
+        }
+        let changed = run_swarm(
+            window,
+            swarm,
+            reconnects,
+            &mut response_rcv,
+            &mut command_rcv,
+        )
+        .await;
+        // the old swarm was dropped along with its listeners and connections
+        forget_network_state(window);
+        polls = changed.polls;
+        pending_reply = Some(changed.reply);
+    }
+}
+
+/// Builds the swarm of the current node key, subscribed to `polls`, then listens, dials the
+/// known peers and starts the initial sync
+async fn start_swarm(
+    window: &Window,
+    response_sender: mpsc::UnboundedSender<OutgoingResponse>,
+    polls: &[String],
+) -> (Swarm<VoteBehaviour>, Reconnects) {
+    let keys = node_keys();
+    let peer_id = local_peer_id();
+    info!("Peer Id: {}", peer_id);
+    *LOG_CONTEXT.write().expect("log context lock is poisoned") =
+        Some(format!("{}@{}", peer_id, *TOPIC));
+
+    let transport_kind = env_or(TRANSPORT_ENV, TransportKind::Tcp);
+    let transp = build_transport(
+        &keys,
+        transport_kind,
+        env_or(HANDSHAKE_ENV, HandshakePattern::XX),
+    );
+
+    let behaviour::Behaviour {
+        mdns,
+        gossipsub,
+        ping,
+    } = behaviour::Behaviour::with_mdns_config(
+        keys,
+        (!*LOCAL_ONLY).then(behaviour::mdns_config_from_env),
+    )
+    .await;
+    let mut behaviour = VoteBehaviour {
+        gossipsub,
+        mdns,
+        ping,
+        response_sender,
+        window: window.clone(),
+        peers: window.state::<PeersState>().peers.clone(),
+        seen_messages: RecentKeys::new(Duration::from_secs(env_or(
+            DEDUP_WINDOW_ENV,
+            DEFAULT_DEDUP_WINDOW_SECS,
+        ))),
+        answers: Answers::new(),
+        queries: Queries::new(Duration::from_secs(env_or(
+            QUERY_TIMEOUT_ENV,
+            DEFAULT_QUERY_TIMEOUT_SECS,
+        ))),
+        peer_addrs: PeerAddrs::default(),
+        latencies: window.state::<LatenciesState>().latencies.clone(),
+        ping_failures: HashMap::new(),
+        fetches: HashMap::new(),
+        counters: window.state::<MetricsState>().counters.clone(),
+        sync: None,
+        roster: window.state::<PresenceState>().roster.clone(),
+        // `go_offline` left the default topic
+        offline: !polls.contains(&*TOPIC_NAME),
+    };
+
+    for poll in polls {
+        behaviour
+            .gossipsub
+            .subscribe(&IdentTopic::new(poll.as_str()))
+            .expect("can't subscribe to topic");
+    }
+
+    let mut swarm = SwarmBuilder::new(transp, behaviour, peer_id)
+        .executor(Box::new(|fut| {
+            tokio::spawn(fut);
+        }))
+        .build();
+
+    listen(
+        &mut swarm,
+        window,
+        transport_kind,
+        env_or(IP_FAMILIES_ENV, IpFamilies::Dual),
+        env_or(PORT_ENV, 0),
+    );
+
+    let reconnects = Reconnects::new(dial_bootstrap_peers(&mut swarm, window));
+    // local-only nodes don't reach out to LAN addresses
+    if !*LOCAL_ONLY {
+        dial_known_peers(&mut swarm, window);
+    }
+
+    // peers found in the meantime get the request when it's retried
+    if !swarm.behaviour().offline {
+        start_initial_sync(&mut swarm, window);
+    }
+
+    (swarm, reconnects)
+}
+
+/// Clears what the dropped swarm was listening on and connected to
+fn forget_network_state(window: &Window) {
+    window
+        .state::<ListenAddrsState>()
+        .addrs
+        .lock()
+        .expect("listen addrs lock is poisoned")
+        .clear();
+    window
+        .state::<LatenciesState>()
+        .latencies
+        .lock()
+        .expect("latencies lock is poisoned")
+        .clear();
+    update_connections(window, |peers| {
+        let changed = !peers.is_empty();
+        peers.clear();
+        changed
+    });
+}
+
+/// Drives `swarm`, sending responses and running commands, until the node key is replaced
+async fn run_swarm(
+    window: &Window,
+    mut swarm: Swarm<VoteBehaviour>,
+    mut reconnects: Reconnects,
+    response_rcv: &mut mpsc::UnboundedReceiver<OutgoingResponse>,
+    command_rcv: &mut mpsc::UnboundedReceiver<SwarmCommand>,
+) -> IdentityChanged {
+    let mut query_ticks = tokio::time::interval(QUERY_TICK);
+    let mut expiry_ticks = tokio::time::interval(EXPIRY_TICK);
+    let mut heartbeats = tokio::time::interval(presence_interval());
+
+    loop {
+        let evt = {
+            tokio::select! {
+                response = response_rcv.recv() => Some(EventType::Response(response.expect("response doesn't exist"))),
+                command = command_rcv.recv() => Some(EventType::Command(command.expect("command doesn't exist"))),
+                _ = query_ticks.tick() => Some(EventType::QueryTick),
+                _ = expiry_ticks.tick() => Some(EventType::ExpiryTick),
+                _ = heartbeats.tick() => Some(EventType::Heartbeat),
+                event = swarm.select_next_some() => {
+                    handle_swarm_event(window, &mut reconnects, event);
+                    None
+                },
+            }
+        };
+
+        if let Some(event) = evt {
+            match event {
+                EventType::Response(OutgoingResponse {
+                    poll,
+                    response,
+                    target,
+                }) => {
+                    if let Err(e) = send_response(&mut swarm, window, &poll, &response, target) {
+                        error!("{}", e);
+                    }
+                }
+                EventType::QueryTick => {
+                    handle_query_updates(&mut swarm, window);
+                    redial_due(&mut swarm, window, &mut reconnects);
+                }
+                EventType::Heartbeat => send_heartbeat(&mut swarm, window),
+                EventType::ExpiryTick => prune_expired(&swarm, window),
+                EventType::Command(SwarmCommand::Dial { address, reply }) => {
+                    let _ = reply.send(dial(&mut swarm, window, address));
+                }
+                EventType::Command(SwarmCommand::JoinPoll { topic, reply }) => {
+                    let gossipsub = &mut swarm.behaviour_mut().gossipsub;
+                    let _ = reply.send(join_or_leave_poll(gossipsub, &topic, true));
+                }
+                EventType::Command(SwarmCommand::LeavePoll { topic, reply }) => {
+                    let gossipsub = &mut swarm.behaviour_mut().gossipsub;
+                    let _ = reply.send(join_or_leave_poll(gossipsub, &topic, false));
+                }
+                EventType::Command(SwarmCommand::SetOnline { online, reply }) => {
+                    let _ = reply.send(set_online(&mut swarm, window, online));
+                }
+                EventType::Command(SwarmCommand::FetchPeerVotes {
+                    peer_id,
+                    poll,
+                    reply,
+                }) => fetch_votes_from(&mut swarm, peer_id, &poll, reply),
+                EventType::Command(SwarmCommand::CancelQuery { request_id, reply }) => {
+                    let _ = reply.send(cancel_query_by_id(&mut swarm, &request_id));
+                }
+                EventType::Command(SwarmCommand::SubscribedPeers { poll, reply }) => {
+                    // the caller may have gone away, nothing to do then
+                    let _ = reply.send(subscribed_peers_of(&swarm.behaviour().gossipsub, &poll));
+                }
+                EventType::Command(SwarmCommand::Cli(command)) => {
+                    handle_command(command, &mut swarm)
+                }
+                EventType::Command(SwarmCommand::Rebroadcast { poll, reply }) => {
+                    let _ = reply.send(rebroadcast_votes(&mut swarm, window, &poll));
+                }
+                EventType::Command(SwarmCommand::RegenerateIdentity { reply }) => {
+                    match node_key_store().and_then(|store| regenerate_keys(store.as_ref())) {
+                        Ok(keys) => {
+                            let previous = local_peer_id();
+                            *IDENTITY.write().expect("identity lock is poisoned") =
+                                Identity::new(keys);
+                            info!(
+                                "Replaced node key, {} is now {}, restarting the swarm",
+                                previous,
+                                local_peer_id()
+                            );
+                            let polls = swarm
+                                .behaviour()
+                                .gossipsub
+                                .topics()
+                                .map(|topic| topic.as_str().to_owned())
+                                .collect();
+                            return IdentityChanged { polls, reply };
+                        }
+                        Err(e) => {
+                            error!("error regenerating node key: {}", e);
+                            let _ = reply.send(Err(e.to_string()));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn format_record(buf: &mut dyn Write, record: &log::Record) -> io::Result<()> {
+    match LOG_CONTEXT
+        .read()
+        .expect("log context lock is poisoned")
+        .as_deref()
+    {
+        Some(context) => writeln!(
+            buf,
+            "{:<5} {} > [{}] {}",
+            record.level(),
+            record.target(),
+            context,
+            record.args()
+        ),
+        None => writeln!(
+            buf,
+            "{:<5} {} > {}",
+            record.level(),
+            record.target(),
+            record.args()
+        ),
+    }
+}
+
+/// Log file, relative paths being resolved in the data dir. Logs only go to stderr when unset.
+const LOG_FILE_ENV: &str = "VOTINGDAPP_LOG_FILE";
+/// Past this size the log file is moved to `<log file>.1`, replacing the previous one
+const MAX_LOG_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+static LOG_PATH: OnceCell<PathBuf> = OnceCell::new();
+
+/// Logs to stderr like before, and to a file the user can attach to bug reports
+struct TeeLogger {
+    stderr: env_logger::Logger,
+    file: Mutex<(fs::File, u64)>,
+    path: PathBuf,
+}
+
+impl TeeLogger {
+    fn open(path: &Path) -> io::Result<(fs::File, u64)> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let len = file.metadata()?.len();
+        Ok((file, len))
+    }
+
+    fn rotate(&self, file: &mut (fs::File, u64)) -> io::Result<()> {
+        fs::rename(&self.path, format!("{}.1", self.path.display()))?;
+        *file = Self::open(&self.path)?;
+        Ok(())
+    }
+}
+
+impl log::Log for TeeLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.stderr.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.stderr.matches(record) {
+            return;
+        }
+        self.stderr.log(record);
+        let mut line = vec![];
+        if format_record(&mut line, record).is_err() {
+            return;
+        }
+        let mut file = self.file.lock().expect("log file lock is poisoned");
+        if file.1 + line.len() as u64 > MAX_LOG_FILE_BYTES && self.rotate(&mut file).is_err() {
+            return;
+        }
+        // nowhere left to report a failure to log
+        if file.0.write_all(&line).is_ok() {
+            file.1 += line.len() as u64;
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+        let _ = self
+            .file
+            .lock()
+            .expect("log file lock is poisoned")
+            .0
+            .flush();
+    }
+}
+
+fn log_file_path() -> Option<PathBuf> {
+    let path = PathBuf::from(env::var(LOG_FILE_ENV).ok()?);
+    if path.is_absolute() {
+        Some(path)
+    } else {
+        Some(data_dir()?.join(path))
+    }
+}
+
+fn init_logger() {
+    let mut builder = pretty_env_logger::formatted_builder();
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    builder.format(|buf, record| format_record(buf, record));
+
+    let path = match log_file_path() {
+        Some(path) => path,
+        None => return builder.init(),
+    };
+    let stderr = builder.build();
+    match TeeLogger::open(&path) {
+        Ok(file) => {
+            let max_level = stderr.filter();
+            let logger = TeeLogger {
+                stderr,
+                file: Mutex::new(file),
+                path: path.clone(),
+            };
+            log::set_boxed_logger(Box::new(logger)).expect("logger is already set");
+            log::set_max_level(max_level);
+            LOG_PATH.get_or_init(|| path);
+        }
+        Err(e) => {
+            let max_level = stderr.filter();
+            log::set_boxed_logger(Box::new(stderr)).expect("logger is already set");
+            log::set_max_level(max_level);
+            error!("cannot open log file {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Where logs are written, when `VOTINGDAPP_LOG_FILE` is set
+#[tauri::command]
+fn get_log_path() -> Option<String> {
+    LOG_PATH.get().map(|path| path.display().to_string())
+}
+
+fn main() {
+    init_logger();
+    log_data_dir();
+
+    if let Some(peer_name) = parse_peer_name(env::args().skip(1)) {
+        info!("Running as peer {}", peer_name);
+        PEER_NAME
+            .set(peer_name)
+            .expect("peer name is only set once");
+    }
+
+    tauri::Builder::default()
+        .manage(PeersState::default())
+        .manage(ListenAddrsState::default())
+        .manage(ConnectionsState::default())
+        .manage(LatenciesState::default())
+        .manage(PresenceState {
+            roster: Arc::new(Mutex::new(Roster::new(presence_interval()))),
+        })
+        .manage(MetricsState::default())
+        .manage(RebroadcastState::default())
+        .setup(|app| {
+            #[cfg(debug_assertions)]
+            app.get_window("main").unwrap().open_devtools();
+
+            let window = app.get_window("main").unwrap();
+
+            let wintwo = app.get_window("main").unwrap();
+
+            // sends every vote at once, fine for small polls, see `get_votes_page` for large ones.
+            // `get_languages` is also sent for frontends predating the command of the same name
+            app.get_window("main").unwrap().listen("ping", move |_| {
+                if let Err(e) = emit_languages(&wintwo) {
+                    error!("error emitting get_languages event, {}", e);
+                }
+                if let Err(e) = emit_votes(&wintwo, &TOPIC_NAME) {
+                    error!("error emitting get_votes event, {}", e);
+                }
+            });
+
+            tauri::async_runtime::spawn(async move {
+                initialize(&window).await;
             });
 
-            Ok(())
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            on_publish_vote,
+            get_languages,
+            add_language,
+            remove_language,
+            list_peers,
+            online_peers,
+            peer_labels,
+            peer_latencies,
+            get_local_votes,
+            watch_votes,
+            get_remote_votes,
+            dial_peer,
+            tally_votes,
+            stats_over_time,
+            get_votes_sorted,
+            export_votes,
+            import_votes,
+            get_peer_id,
+            regenerate_identity,
+            is_local_only,
+            is_dry_run,
+            is_observer,
+            forget_peers,
+            get_metrics,
+            read_audit,
+            get_log_path,
+            health,
+            get_listen_addrs,
+            get_connection_string,
+            connect_via_string,
+            get_votes_page,
+            reset_votes,
+            restore_backup,
+            set_vote_visibility,
+            set_vote_expiry,
+            publish_votes,
+            rebroadcast,
+            rename_vote,
+            delete_votes_by_language,
+            fetch_peer_votes,
+            subscribed_peers,
+            cancel_query,
+            join_poll,
+            leave_poll,
+            go_offline,
+            go_online
+        ])
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::mdns::MdnsConfig;
+    use std::sync::PoisonError;
+
+    /// Held by tests pointing the storage somewhere, the path being process-wide
+    static STORAGE_ENV: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+    /// Storage redirected to a temporary directory, until dropped
+    struct TempStorage {
+        dir: tempfile::TempDir,
+        _guard: MutexGuard<'static, ()>,
+    }
+
+    impl TempStorage {
+        fn new() -> Self {
+            // a failed test must not fail the following ones
+            let guard = STORAGE_ENV.lock().unwrap_or_else(PoisonError::into_inner);
+            let dir = tempfile::tempdir().expect("cannot create a temporary directory");
+            env::set_var(STORAGE_PATH_ENV, dir.path().join(STORAGE_FILE_NAME));
+            Self { dir, _guard: guard }
+        }
+
+        fn votes_file(&self) -> PathBuf {
+            self.dir.path().join(STORAGE_FILE_NAME)
+        }
+    }
+
+    #[test]
+    fn votes_round_trip_through_storage() {
+        let _storage = TempStorage::new();
+        let ids: Vec<usize> = ["Rust", "Elm", "OCaml"]
+            .iter()
+            .map(|name| add_vote(&TOPIC_NAME, name, None, MIN_WEIGHT).unwrap().id)
+            .collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+
+        set_visibility(&TOPIC_NAME, 1, true).unwrap();
+
+        let votes = read_local_votes().unwrap();
+        assert_eq!(votes.len(), 3);
+        let public: Vec<usize> = votes.iter().filter(|r| r.public).map(|r| r.id).collect();
+        assert_eq!(public, vec![1]);
+
+        write_local_votes(&votes).unwrap();
+        let reloaded = read_local_votes().unwrap();
+        assert_eq!(
+            reloaded.iter().map(|r| &r.name).collect::<Vec<_>>(),
+            vec!["Rust", "Elm", "OCaml"]
+        );
+    }
+
+    #[test]
+    fn corrupt_votes_file_is_moved_aside() {
+        let storage = TempStorage::new();
+        fs::write(storage.votes_file(), b"{ not json").unwrap();
+
+        assert!(read_local_votes().unwrap().is_empty());
+        assert!(!storage.votes_file().exists());
+        let corrupt = format!("{}.corrupt", storage.votes_file().display());
+        assert_eq!(fs::read(corrupt).unwrap(), b"{ not json");
+
+        // usable again right away
+        add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT).unwrap();
+        assert_eq!(read_local_votes().unwrap().len(), 1);
+    }
+
+    fn test_vote(id: usize, name: &str, public: bool) -> Vote {
+        Vote {
+            id,
+            name: name.to_owned(),
+            public,
+            created_at: 1_650_000_000_000,
+            signature: vec![],
+            comment: None,
+            weight: MIN_WEIGHT,
+            expires_at: None,
+        }
+    }
+
+    fn encodings<T: Serialize>(message: &T) -> Vec<Vec<u8>> {
+        vec![
+            WireFormat::Json.encode(message).unwrap(),
+            WireFormat::Cbor.encode(message).unwrap(),
+        ]
+    }
+
+    #[test]
+    fn classifies_requests() {
+        let req = ListRequest {
+            mode: ListMode::One("12D3KooW".to_owned()),
+            request_id: Some("1-0".to_owned()),
+            attempt: 1,
+        };
+        for data in encodings(&req) {
+            match classify_message(&data) {
+                Some(IncomingMessage::Request(parsed)) => {
+                    assert_eq!(parsed.mode, req.mode);
+                    assert_eq!(parsed.request_id, req.request_id);
+                    assert_eq!(parsed.attempt, 1);
+                }
+                other => panic!("expected a request, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn classifies_responses() {
+        let resp = ListResponse {
+            mode: ListMode::ALL,
+            data: vec![test_vote(0, "Rust", true)],
+            receiver: BROADCAST_RECEIVER.to_owned(),
+            request_id: None,
+            nonce: None,
+        };
+        for data in encodings(&resp) {
+            match classify_message(&data) {
+                Some(IncomingMessage::Response(parsed)) => {
+                    assert_eq!(parsed.mode, ListMode::ALL);
+                    assert_eq!(parsed.receiver, BROADCAST_RECEIVER);
+                    assert_eq!(parsed.data.len(), 1);
+                    assert_eq!(parsed.data[0].name, "Rust");
+                }
+                other => panic!("expected a response, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn broadcast_responses_are_ingested() {
+        let _storage = TempStorage::new();
+        let keys = identity::Keypair::generate_ed25519();
+        let source = PeerId::from(keys.public());
+        let resp = ListResponse {
+            mode: ListMode::ALL,
+            data: vec![sign_vote(&keys, test_vote(0, "Rust", true)).unwrap()],
+            receiver: BROADCAST_RECEIVER.to_owned(),
+            request_id: None,
+            nonce: None,
+        };
+        let data = WireFormat::Json.encode(&resp).unwrap();
+        let resp = match classify_message(&data) {
+            Some(IncomingMessage::Response(resp)) => resp,
+            other => panic!("expected a response, got {:?}", other),
+        };
+
+        // addressed to whoever reads it, on the topic it came through
+        assert!(is_addressed_to(&resp.receiver, &local_peer_id()));
+        assert!(is_addressed_to(&resp.receiver, &PeerId::random()));
+        let topic = TOPIC.hash();
+        let poll = response_poll(&resp, &topic, None, |_| false).unwrap();
+        assert_eq!(poll, TOPIC_NAME.as_str());
+
+        assert!(resp.data.iter().all(|v| verify_vote(v, &source)));
+        let added = merge_remote_votes(poll, &source.to_string(), resp.data.clone()).unwrap();
+        assert_eq!(added.len(), 1);
+        let stored = read_remote_votes(poll).unwrap();
+        assert_eq!(stored[&source.to_string()][0].name, "Rust");
+    }
+
+    #[test]
+    fn classifies_presence() {
+        let presence = Presence {
+            peer_id: "12D3KooW".to_owned(),
+            timestamp: 1_650_000_000_000,
+            display_name: Some("alice".to_owned()),
+        };
+        for data in encodings(&presence) {
+            match classify_message(&data) {
+                Some(IncomingMessage::Presence(parsed)) => {
+                    assert_eq!(parsed.peer_id, presence.peer_id);
+                    assert_eq!(parsed.display_name, presence.display_name);
+                }
+                other => panic!("expected a presence, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn requests_from_older_peers_get_defaults() {
+        match classify_message(br#"{"mode":"ALL"}"#) {
+            Some(IncomingMessage::Request(req)) => {
+                assert_eq!(req.request_id, None);
+                assert_eq!(req.attempt, 0);
+            }
+            other => panic!("expected a request, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn garbage_is_not_classified() {
+        for data in [
+            &b""[..],
+            &b"not a message"[..],
+            &[0xff; 16][..],
+            &br#"{"mode":"SOME"}"#[..],
+            &br#"{"peer_id":"12D3KooW"}"#[..],
+        ] {
+            assert!(classify_message(data).is_none(), "{:?}", data);
+        }
+    }
+
+    async fn memory_swarm() -> Swarm<behaviour::Behaviour> {
+        let keys = identity::Keypair::generate_ed25519();
+        let peer_id = PeerId::from(keys.public());
+        let transport = build_transport(&keys, TransportKind::Memory, HandshakePattern::XX);
+        let behaviour = behaviour::Behaviour::with_mdns_config(keys, None).await;
+        SwarmBuilder::new(transport, behaviour, peer_id)
+            .executor(Box::new(|fut| {
+                tokio::spawn(fut);
+            }))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn memory_swarms_exchange_responses() {
+        let topic = IdentTopic::new("memory-test");
+        let mut sender = memory_swarm().await;
+        let mut receiver = memory_swarm().await;
+        sender.behaviour_mut().gossipsub.subscribe(&topic).unwrap();
+        receiver
+            .behaviour_mut()
+            .gossipsub
+            .subscribe(&topic)
+            .unwrap();
+        sender.listen_on("/memory/0".parse().unwrap()).unwrap();
+
+        let resp = ListResponse {
+            mode: ListMode::ALL,
+            data: vec![test_vote(0, "Rust", true)],
+            receiver: BROADCAST_RECEIVER.to_owned(),
+            request_id: None,
+            nonce: None,
+        };
+        let payload = WireFormat::Json.encode(&resp).unwrap();
+
+        let received = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                tokio::select! {
+                    event = sender.select_next_some() => match event {
+                        SwarmEvent::NewListenAddr { address, .. } => {
+                            receiver.dial_addr(address).unwrap()
+                        }
+                        // gossipsub only publishes to peers known to be subscribed
+                        SwarmEvent::Behaviour(behaviour::BehaviourEvent::GossipsubEvent(
+                            GossipsubEvent::Subscribed { .. },
+                        )) => {
+                            sender
+                                .behaviour_mut()
+                                .gossipsub
+                                .publish(topic.clone(), payload.clone())
+                                .unwrap();
+                        }
+                        _ => {}
+                    },
+                    event = receiver.select_next_some() => {
+                        if let SwarmEvent::Behaviour(behaviour::BehaviourEvent::GossipsubEvent(
+                            GossipsubEvent::Message { message, .. },
+                        )) = event
+                        {
+                            break classify_message(&message.data);
+                        }
+                    }
+                }
+            }
+        })
+        .await
+        .expect("no response received in time");
+
+        match received {
+            Some(IncomingMessage::Response(resp)) => {
+                assert_eq!(resp.data.len(), 1);
+                assert_eq!(resp.data[0].name, "Rust");
+            }
+            other => panic!("expected a response, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn private_votes_are_never_shared() {
+        let keys = identity::Keypair::generate_ed25519();
+        let votes = vec![
+            test_vote(0, "Rust", true),
+            test_vote(1, "Haskell", false),
+            test_vote(2, "Elm", true),
+            test_vote(3, "Clojure", false),
+        ];
+        let resp = ListResponse {
+            mode: ListMode::ALL,
+            data: shareable_votes(&keys, votes).unwrap(),
+            receiver: BROADCAST_RECEIVER.to_owned(),
+            request_id: None,
+            nonce: None,
+        };
+        assert_eq!(resp.data.len(), 2);
+
+        let json = String::from_utf8(WireFormat::Json.encode(&resp).unwrap()).unwrap();
+        assert!(json.contains("Rust") && json.contains("Elm"));
+        assert!(!json.contains("Haskell"), "private vote shared: {}", json);
+        assert!(!json.contains("Clojure"), "private vote shared: {}", json);
+    }
+
+    #[test]
+    fn signed_votes_verify_against_their_author() {
+        let keys = identity::Keypair::generate_ed25519();
+        let author = PeerId::from(keys.public());
+        let vote = sign_vote(&keys, test_vote(0, "Rust", true)).unwrap();
+        assert!(verify_vote(&vote, &author));
+
+        let other = PeerId::from(identity::Keypair::generate_ed25519().public());
+        assert!(!verify_vote(&vote, &other));
+    }
+
+    #[test]
+    fn tampered_votes_fail_verification() {
+        let keys = identity::Keypair::generate_ed25519();
+        let author = PeerId::from(keys.public());
+        let vote = sign_vote(&keys, test_vote(0, "Rust", true)).unwrap();
+
+        let renamed = Vote {
+            name: "Go".to_owned(),
+            ..vote.clone()
+        };
+        assert!(!verify_vote(&renamed, &author));
+        let reweighted = Vote {
+            weight: MAX_WEIGHT,
+            ..vote.clone()
+        };
+        assert!(!verify_vote(&reweighted, &author));
+        let unsigned = Vote {
+            signature: vec![],
+            ..vote
+        };
+        assert!(!verify_vote(&unsigned, &author));
+    }
+
+    #[test]
+    fn same_answer_from_two_peers_is_not_a_duplicate() {
+        let first = PeerId::random();
+        let second = PeerId::random();
+        let data = br#"{"mode":"ALL","data":[],"receiver":"any"}"#;
+        let mut seen = RecentKeys::new(Duration::from_secs(60));
+        assert!(seen.insert(message_hash(&first, data)));
+        assert!(seen.insert(message_hash(&second, data)));
+        assert!(!seen.insert(message_hash(&first, data)));
+    }
+
+    fn languages(names: &[&str]) -> Vec<Language> {
+        names
+            .iter()
+            .map(|&name| Language {
+                name: name.to_owned(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tally_lists_every_language_most_voted_first() {
+        let votes = vec![
+            test_vote(0, "Elm", false),
+            test_vote(1, "Elm", true),
+            test_vote(2, "Go", true),
+        ];
+        let counts = tally(&languages(&["Rust", "Go", "Elm"]), &votes, TallyMode::Count);
+        assert_eq!(
+            counts,
+            vec![
+                ("Elm".to_owned(), 2),
+                ("Go".to_owned(), 1),
+                ("Rust".to_owned(), 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn tally_keeps_language_order_on_ties_and_counts_unknown_ones() {
+        let votes = vec![test_vote(0, "Zig", true), test_vote(1, "Go", true)];
+        let counts = tally(&languages(&["Rust", "Go"]), &votes, TallyMode::Count);
+        assert_eq!(
+            counts,
+            vec![
+                ("Go".to_owned(), 1),
+                ("Zig".to_owned(), 1),
+                ("Rust".to_owned(), 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn weighted_tally_sums_weights() {
+        let votes = vec![
+            Vote {
+                weight: 4,
+                ..test_vote(0, "Rust", true)
+            },
+            test_vote(1, "Go", true),
+            test_vote(2, "Go", true),
+        ];
+        let languages = languages(&["Go", "Rust"]);
+        assert_eq!(
+            tally(&languages, &votes, TallyMode::Weighted),
+            vec![("Rust".to_owned(), 4), ("Go".to_owned(), 2)]
+        );
+        assert_eq!(
+            tally(&languages, &votes, TallyMode::Count),
+            vec![("Go".to_owned(), 2), ("Rust".to_owned(), 1)]
+        );
+    }
+
+    fn migrate_json(json: &str) -> std::result::Result<Storage, StorageError> {
+        migrate_storage(serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn legacy_vote_arrays_are_migrated() {
+        let storage = migrate_json(
+            r#"[{"id":0,"name":"Rust","public":true},{"id":4,"name":"Go","public":false}]"#,
+        )
+        .unwrap();
+        assert_eq!(storage.version, STORAGE_VERSION);
+        assert_eq!(storage.votes.len(), 2);
+        assert_eq!(storage.votes[0].created_at, 0);
+        assert_eq!(storage.votes[0].weight, MIN_WEIGHT);
+        assert_eq!(storage.next_id, 5);
+    }
+
+    #[test]
+    fn id_counter_carries_on_from_older_versions() {
+        let storage =
+            migrate_json(r#"{"version":1,"votes":[{"id":2,"name":"Rust","public":true}]}"#)
+                .unwrap();
+        assert_eq!(storage.next_id, 3);
+
+        // ids of deleted votes aren't reused
+        let storage = migrate_json(r#"{"version":2,"votes":[],"next_id":7}"#).unwrap();
+        assert_eq!(storage.next_id, 7);
+    }
+
+    #[test]
+    fn newer_storage_versions_are_refused() {
+        let migrated = migrate_json(&format!(
+            r#"{{"version":{},"votes":[]}}"#,
+            STORAGE_VERSION + 1
+        ));
+        assert!(matches!(migrated, Err(StorageError::Corrupt(_))));
+    }
+
+    #[test]
+    fn parses_stdin_commands() {
+        assert_eq!("ls p".parse(), Ok(Command::ListPeers));
+        assert_eq!("  ls   v \n".parse(), Ok(Command::ListLocal));
+        assert_eq!("ls v all".parse(), Ok(Command::ListVotesAll));
+        let peer_id = PeerId::random().to_string();
+        assert_eq!(
+            format!("ls v {}", peer_id).parse(),
+            Ok(Command::ListVotesFrom(peer_id))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        for line in ["", "ls", "ls x", "ls v all now", "rm v"] {
+            assert!(line.parse::<Command>().is_err(), "{:?}", line);
+        }
+        assert!("ls v not-a-peer-id".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn recent_keys_are_seen_once_within_the_window() {
+        let mut keys = RecentKeys::new(Duration::from_secs(60));
+        assert!(keys.insert("a"));
+        assert!(keys.insert("b"));
+        assert!(!keys.insert("a"));
+        assert!(!keys.insert("b"));
+    }
+
+    #[test]
+    fn recent_keys_are_forgotten_after_the_window() {
+        let mut keys = RecentKeys::new(Duration::from_millis(20));
+        assert!(keys.insert("a"));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(keys.insert("a"));
+        assert_eq!(keys.seen.len(), 1);
+    }
+
+    fn local_vote_names() -> Vec<String> {
+        read_local_votes()
+            .unwrap()
+            .into_iter()
+            .map(|r| r.name)
+            .collect()
+    }
+
+    #[test]
+    fn invalid_imports_leave_votes_intact() {
+        let storage = TempStorage::new();
+        add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT).unwrap();
+        let file = storage.dir.path().join("import.json");
+        let write = |votes: &[Vote]| fs::write(&file, serde_json::to_vec(votes).unwrap()).unwrap();
+
+        write(&[test_vote(0, "Go", true), test_vote(1, "Brainfuck", true)]);
+        assert!(import_votes_from(&file, ImportMode::Replace).is_err());
+        let overweight = Vote {
+            weight: MAX_WEIGHT + 1,
+            ..test_vote(1, "Elm", true)
+        };
+        write(&[test_vote(0, "Go", true), overweight]);
+        assert!(import_votes_from(&file, ImportMode::Merge).is_err());
+        write(&[test_vote(0, "Go", true), test_vote(1, "Go", false)]);
+        assert!(import_votes_from(&file, ImportMode::Replace).is_err());
+
+        assert_eq!(local_vote_names(), vec!["Rust"]);
+    }
+
+    #[test]
+    fn imports_are_audited() {
+        let storage = TempStorage::new();
+        add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT).unwrap();
+        let file = storage.dir.path().join("import.json");
+        let votes = vec![test_vote(0, "Go", true), test_vote(1, "Rust", true)];
+        fs::write(&file, serde_json::to_vec(&votes).unwrap()).unwrap();
+
+        assert_eq!(import_votes_from(&file, ImportMode::Merge).unwrap(), 1);
+        assert_eq!(local_vote_names(), vec!["Rust", "Go"]);
+        let entries = read_audit_entries(10).unwrap();
+        let last = entries.last().unwrap();
+        assert!(matches!(last.operation, AuditOperation::Import));
+        assert_eq!(last.vote_id, Some(1));
+
+        assert_eq!(import_votes_from(&file, ImportMode::Replace).unwrap(), 2);
+        let operations: Vec<_> = read_audit_entries(3)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.operation)
+            .collect();
+        assert!(matches!(
+            operations.as_slice(),
+            [
+                AuditOperation::Reset,
+                AuditOperation::Import,
+                AuditOperation::Import
+            ]
+        ));
+    }
+
+    fn numbered_votes(count: usize) -> Votes {
+        (0..count).map(|id| test_vote(id, "Rust", true)).collect()
+    }
+
+    #[test]
+    fn pages_through_votes() {
+        let page = votes_page(numbered_votes(10), 4, 3);
+        assert_eq!(page.total, 10);
+        assert_eq!(
+            page.items.iter().map(|r| r.id).collect::<Vec<_>>(),
+            vec![4, 5, 6]
+        );
+
+        let last = votes_page(numbered_votes(10), 8, 5);
+        assert_eq!(last.items.len(), 2);
+        assert!(votes_page(numbered_votes(10), 20, 5).items.is_empty());
+    }
+
+    #[test]
+    fn page_size_is_capped() {
+        let page = votes_page(numbered_votes(MAX_PAGE_SIZE + 10), 0, usize::MAX);
+        assert_eq!(page.items.len(), MAX_PAGE_SIZE);
+        assert_eq!(page.total, MAX_PAGE_SIZE + 10);
+    }
+
+    #[test]
+    fn parses_peer_ids() {
+        let peer_id = PeerId::random();
+        assert_eq!(parse_peer_id(&peer_id.to_string()), Ok(peer_id));
+        assert_eq!(parse_peer_id(&format!(" {}\n", peer_id)), Ok(peer_id));
+        assert!(parse_peer_id("").is_err());
+        assert!(parse_peer_id("not-a-peer-id").is_err());
+    }
+
+    #[test]
+    fn connection_strings_round_trip() {
+        let address: Multiaddr = format!("/ip4/192.168.1.20/tcp/4001/p2p/{}", PeerId::random())
+            .parse()
+            .unwrap();
+        let encoded = encode_connection_string(&address);
+        assert!(encoded.starts_with(CONNECTION_STRING_PREFIX));
+        assert_eq!(decode_connection_string(&encoded), Ok(address.clone()));
+        assert_eq!(
+            decode_connection_string(&format!("  {}\n", encoded)),
+            Ok(address)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_connection_strings() {
+        let without_peer: Multiaddr = "/ip4/192.168.1.20/tcp/4001".parse().unwrap();
+        for s in [
+            "".to_owned(),
+            "vote:".to_owned(),
+            "vote:0OIl".to_owned(),
+            bs58::encode(without_peer.to_vec()).into_string(),
+            encode_connection_string(&without_peer),
+        ] {
+            assert!(decode_connection_string(&s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn add_vote_failures_have_their_own_variant() {
+        let _storage = TempStorage::new();
+        add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT).unwrap();
+
+        assert!(matches!(
+            add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT),
+            Err(AddVoteError::Duplicate(name)) if name == "Rust"
+        ));
+        assert!(matches!(
+            add_vote(&TOPIC_NAME, "Brainfuck", None, MIN_WEIGHT),
+            Err(AddVoteError::UnknownLanguage(name)) if name == "Brainfuck"
+        ));
+        assert!(matches!(
+            add_vote(&TOPIC_NAME, "Go", None, MAX_WEIGHT + 1),
+            Err(AddVoteError::InvalidWeight(weight)) if weight == MAX_WEIGHT + 1
+        ));
+        let comment = "a".repeat(MAX_COMMENT_CHARS + 1);
+        assert!(matches!(
+            add_vote(&TOPIC_NAME, "Go", Some(&comment), MIN_WEIGHT),
+            Err(AddVoteError::CommentTooLong(MAX_COMMENT_CHARS))
+        ));
+
+        env::set_var(MAX_VOTES_ENV, "1");
+        let over_limit = add_vote(&TOPIC_NAME, "Go", None, MIN_WEIGHT);
+        env::remove_var(MAX_VOTES_ENV);
+        assert!(matches!(over_limit, Err(AddVoteError::LimitExceeded(1))));
+    }
+
+    #[test]
+    fn unreadable_storage_is_a_storage_error() {
+        let storage = TempStorage::new();
+        // encrypted, while no key is configured
+        fs::write(
+            storage.votes_file(),
+            [at_rest::MAGIC, &[0u8; 64][..]].concat(),
+        )
+        .unwrap();
+        assert!(matches!(
+            add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT),
+            Err(AddVoteError::Storage(StorageError::Corrupt(_)))
+        ));
+        assert!(matches!(
+            set_visibility(&TOPIC_NAME, 0, true),
+            Err(ChangeVoteError::Storage(StorageError::Corrupt(_)))
+        ));
+
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        assert!(matches!(
+            storage_write_error("votes.json", denied),
+            StorageError::PermissionDenied { path } if path == "votes.json"
+        ));
+        let full = io::Error::new(io::ErrorKind::Other, "disk full");
+        assert!(matches!(
+            storage_write_error("votes.json", full),
+            StorageError::Io(_)
+        ));
+    }
+
+    #[test]
+    fn vote_change_failures_have_their_own_variant() {
+        let _storage = TempStorage::new();
+        add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT).unwrap();
+        add_vote(&TOPIC_NAME, "Go", None, MIN_WEIGHT).unwrap();
+
+        assert!(matches!(
+            set_visibility(&TOPIC_NAME, 42, true),
+            Err(ChangeVoteError::NotFound(42))
+        ));
+        assert!(matches!(
+            set_expiry(&TOPIC_NAME, 42, None),
+            Err(ChangeVoteError::NotFound(42))
+        ));
+        assert!(matches!(
+            rename_local_vote(&TOPIC_NAME, 42, "Elm"),
+            Err(ChangeVoteError::NotFound(42))
+        ));
+        assert!(matches!(
+            rename_local_vote(&TOPIC_NAME, 0, "Go"),
+            Err(ChangeVoteError::Duplicate(name)) if name == "Go"
+        ));
+        assert!(matches!(
+            rename_local_vote(&TOPIC_NAME, 0, "Brainfuck"),
+            Err(ChangeVoteError::UnknownLanguage(_))
+        ));
+        // unknown ids are skipped rather than failing the whole batch
+        assert_eq!(publish_local_votes(&TOPIC_NAME, &[1, 42]).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn large_exports_parse_back() {
+        let storage = TempStorage::new();
+        let count = DEFAULT_MAX_VOTES;
+        let votes: Votes = (0..count)
+            .map(|id| Vote {
+                comment: Some(format!("vote, \"number\" {}", id)),
+                ..test_vote(id, "Rust", id % 2 == 0)
+            })
+            .collect();
+        write_local_votes(&votes).unwrap();
+
+        let json = storage.dir.path().join("export.json");
+        export_votes_to(&json, ExportFormat::Json).unwrap();
+        let exported: Votes = serde_json::from_slice(&fs::read(&json).unwrap()).unwrap();
+        assert_eq!(exported.len(), count);
+        assert!(exported.iter().enumerate().all(|(i, r)| r.id == i));
+
+        let csv = storage.dir.path().join("export.csv");
+        export_votes_to(&csv, ExportFormat::Csv).unwrap();
+        let csv = fs::read_to_string(&csv).unwrap();
+        assert_eq!(csv.lines().count(), count + 1);
+        assert_eq!(
+            csv.lines().nth(1),
+            Some("0,Rust,true,1650000000000,\"vote, \"\"number\"\" 0\",1")
+        );
+    }
+
+    fn vote_at(id: usize, created_at: u64) -> Vote {
+        Vote {
+            created_at,
+            ..test_vote(id, "Rust", true)
+        }
+    }
+
+    #[test]
+    fn histogram_counts_votes_per_bucket_including_empty_ones() {
+        let votes = vec![
+            vote_at(0, 3_600_000),
+            vote_at(1, 3_660_000),
+            vote_at(2, 3_600_000 + 3 * 60_000),
+        ];
+        assert_eq!(
+            histogram(&votes, 60).unwrap(),
+            vec![(3_600, 1), (3_660, 1), (3_720, 0), (3_780, 1)]
+        );
+        assert_eq!(histogram(&votes, 3_600).unwrap(), vec![(3_600, 3)]);
+        assert!(histogram(&[], 60).unwrap().is_empty());
+        assert!(histogram(&votes, 0).is_err());
+    }
+
+    #[test]
+    fn histogram_skips_undated_votes() {
+        let now = 1_650_000_000_000;
+        let votes = vec![vote_at(0, 0), vote_at(1, now), vote_at(2, now + 1_000)];
+        assert_eq!(
+            histogram(&votes, 60).unwrap(),
+            vec![(now / 1000 / 60 * 60, 2)]
+        );
+        assert!(histogram(&[vote_at(0, 0)], 60).unwrap().is_empty());
+    }
+
+    #[test]
+    fn histogram_refuses_too_many_buckets() {
+        let votes = vec![vote_at(0, 1_000), vote_at(1, (MAX_BUCKETS + 1) * 1_000)];
+        assert!(histogram(&votes, 1).is_err());
+        assert!(histogram(&votes, 2).is_ok());
+    }
+
+    #[test]
+    fn regenerated_keys_change_the_peer_id_and_are_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileKeyStore::new(dir.path().join(NODE_KEY_FILE_NAME));
+        let first = PeerId::from(regenerate_keys(&store).unwrap().public());
+        let second = PeerId::from(regenerate_keys(&store).unwrap().public());
+        assert_ne!(first, second);
+
+        let stored = store.load().unwrap().expect("no key was persisted");
+        assert_eq!(PeerId::from(stored.public()), second);
+    }
+
+    #[test]
+    fn rebroadcasts_of_the_same_votes_differ() {
+        let votes = || vec![test_vote(0, "Rust", true)];
+        let first = encodings(&broadcast_response(votes()));
+        let second = encodings(&broadcast_response(votes()));
+        for (first, second) in first.iter().zip(&second) {
+            assert_ne!(first, second);
+            match classify_message(first) {
+                Some(IncomingMessage::Response(parsed)) => assert!(parsed.nonce.is_some()),
+                other => panic!("expected a response, got {:?}", other),
+            }
+        }
+
+        // responses of peers predating the nonce still parse
+        let legacy = br#"{"mode":"ALL","data":[],"receiver":"any","request_id":null,"ttl":8}"#;
+        match classify_message(legacy) {
+            Some(IncomingMessage::Response(parsed)) => assert_eq!(parsed.nonce, None),
+            other => panic!("expected a response, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn first_resolved_dir_wins() {
+        type Resolver = fn() -> Option<PathBuf>;
+        fn none() -> Option<PathBuf> {
+            None
+        }
+        fn data() -> Option<PathBuf> {
+            Some(PathBuf::from("/data"))
+        }
+        fn config() -> Option<PathBuf> {
+            Some(PathBuf::from("/config"))
+        }
+
+        assert_eq!(
+            first_dir(&[("data", data as Resolver), ("config", config as Resolver)]),
+            Some(("data", PathBuf::from("/data")))
+        );
+        assert_eq!(
+            first_dir(&[
+                ("data", none as Resolver),
+                ("none", none as Resolver),
+                ("config", config as Resolver)
+            ]),
+            Some(("config", PathBuf::from("/config")))
+        );
+        assert_eq!(
+            first_dir(&[("data", none as Resolver), ("config", none as Resolver)]),
+            None
+        );
+        assert_eq!(first_dir(&[]), None);
+    }
+
+    #[test]
+    fn expired_votes_are_split_off() {
+        let expiring = |id, expires_at| Vote {
+            expires_at: Some(expires_at),
+            ..test_vote(id, "Rust", true)
+        };
+        let votes = vec![
+            expiring(0, 1_000),
+            expiring(1, 2_000),
+            test_vote(2, "Elm", true),
+            expiring(3, 3_000),
+        ];
+
+        let (expired, kept) = split_expired(votes, 2_000);
+        // expiring at `now` counts as expired
+        assert_eq!(expired.iter().map(|r| r.id).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(kept.iter().map(|r| r.id).collect::<Vec<_>>(), vec![2, 3]);
+
+        let (expired, kept) = split_expired(vec![test_vote(0, "Elm", true)], u64::MAX);
+        assert!(expired.is_empty());
+        assert_eq!(kept.len(), 1);
+    }
+
+    #[test]
+    fn oversized_messages_are_dropped_unparsed() {
+        let mut seen = RecentKeys::new(Duration::from_secs(60));
+        let source = PeerId::random();
+        let mut resp = broadcast_response(vec![test_vote(0, "Rust", true)]);
+        let small = WireFormat::Json.encode(&resp).unwrap();
+        resp.data[0].name = "x".repeat(MAX_MESSAGE_BYTES);
+        let oversized = WireFormat::Json.encode(&resp).unwrap();
+        // it would parse, were it not too large
+        assert!(classify_message(&oversized).is_some());
+
+        assert!(matches!(
+            receive(&mut seen, &source, &oversized),
+            Received::Oversized
+        ));
+        // not even remembered as seen
+        assert!(seen.insert(message_hash(&source, &oversized)));
+        assert!(matches!(
+            receive(&mut seen, &source, &small),
+            Received::Parsed(Some(IncomingMessage::Response(_)))
+        ));
+        assert!(matches!(
+            receive(&mut seen, &source, &small),
+            Received::Duplicate
+        ));
+    }
+
+    #[test]
+    fn subscriptions_are_answered_once_per_interval() {
+        let mut answers = Answers::new();
+        let peer = PeerId::random();
+        assert_eq!(
+            answers.subscription(peer),
+            Some(Answer {
+                receiver: peer.to_string(),
+                mode: ListMode::ALL,
+                target: ResponseTarget::Peer(peer),
+            })
+        );
+        // toggling the subscription doesn't trigger another answer
+        assert_eq!(answers.subscription(peer), None);
+        assert!(answers.subscription(PeerId::random()).is_some());
+
+        // and the startup query of the subscriber is still answered
+        let req = ListRequest {
+            mode: ListMode::ALL,
+            request_id: Some("1-0".to_owned()),
+            attempt: 0,
+        };
+        assert!(answers.request(peer, &req, &PeerId::random()).is_some());
+        assert!(answers.request(peer, &req, &PeerId::random()).is_none());
+    }
+
+    #[tokio::test]
+    async fn subscribing_peers_are_sent_our_public_votes() {
+        let _storage = TempStorage::new();
+        add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT).unwrap();
+        let elm = add_vote(&TOPIC_NAME, "Elm", None, MIN_WEIGHT).unwrap();
+        set_visibility(&TOPIC_NAME, elm.id, true).unwrap();
+
+        let peer = PeerId::random();
+        let Answer {
+            receiver,
+            mode,
+            target,
+        } = Answers::new().subscription(peer).unwrap();
+        let (sender, mut responses) = mpsc::unbounded_channel();
+        respond_with_public_votes(sender, TOPIC_NAME.clone(), receiver, mode, target, None);
+
+        let sent = responses.recv().await.expect("no response was sent");
+        assert_eq!(sent.target, ResponseTarget::Peer(peer));
+        assert_eq!(sent.response.receiver, peer.to_string());
+        assert_eq!(
+            sent.response
+                .data
+                .iter()
+                .map(|r| &r.name)
+                .collect::<Vec<_>>(),
+            vec!["Elm"]
+        );
+    }
+
+    #[test]
+    fn hop_counts_of_older_peers_are_ignored() {
+        // older peers stamp a `ttl`, gossipsub relays messages on its own so there is no hop to
+        // count, even one they sent with none left is handled
+        for ttl in [0, 8] {
+            let req = format!(r#"{{"mode":"ALL","request_id":"1-0","ttl":{}}}"#, ttl);
+            match classify_message(req.as_bytes()) {
+                Some(IncomingMessage::Request(req)) => assert_eq!(req.mode, ListMode::ALL),
+                other => panic!("expected a request, got {:?}", other),
+            }
+            let resp = format!(
+                r#"{{"mode":"ALL","data":[],"receiver":"any","request_id":null,"ttl":{}}}"#,
+                ttl
+            );
+            assert!(matches!(
+                classify_message(resp.as_bytes()),
+                Some(IncomingMessage::Response(_))
+            ));
+        }
+
+        // and they get their default when it's missing from ours
+        let req = ListRequest {
+            mode: ListMode::ALL,
+            request_id: None,
+            attempt: 0,
+        };
+        let json = String::from_utf8(WireFormat::Json.encode(&req).unwrap()).unwrap();
+        assert!(!json.contains("ttl"));
+    }
+
+    #[test]
+    fn concurrent_adds_of_a_vote_store_it_once() {
+        let _storage = TempStorage::new();
+        let start = Arc::new(std::sync::Barrier::new(2));
+        let adds: Vec<_> = (0..2)
+            .map(|_| {
+                let start = start.clone();
+                std::thread::spawn(move || {
+                    start.wait();
+                    add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT)
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|add| add.join().unwrap())
+            .collect();
+
+        assert_eq!(adds.iter().filter(|added| added.is_ok()).count(), 1);
+        assert!(adds
+            .iter()
+            .any(|added| matches!(added, Err(AddVoteError::Duplicate(name)) if name == "Rust")));
+        assert_eq!(read_local_votes().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn topic_responses_are_only_taken_when_asked_for_or_joined() {
+        let topic = TopicHash::from_raw(TOPIC_NAME.as_str());
+        let resp = |mode| ListResponse {
+            mode,
+            data: vec![],
+            receiver: BROADCAST_RECEIVER.to_owned(),
+            request_id: None,
+            nonce: None,
+        };
+        let query = |poll: &str| ListRequest {
+            mode: ListMode::Topic(poll.to_owned()),
+            request_id: Some("1-0".to_owned()),
+            attempt: 0,
+        };
+        let joined = |poll: &str| poll == "joined";
+
+        let all = resp(ListMode::ALL);
+        assert_eq!(
+            response_poll(&all, &topic, None, joined),
+            Some(TOPIC_NAME.as_str())
+        );
+        let asked = resp(ListMode::Topic("asked".to_owned()));
+        assert_eq!(
+            response_poll(&asked, &topic, Some(&query("asked")), joined),
+            Some("asked")
+        );
+        // answering another query doesn't make any poll acceptable
+        assert_eq!(
+            response_poll(&asked, &topic, Some(&query("other")), joined),
+            None
+        );
+        assert_eq!(response_poll(&asked, &topic, None, joined), None);
+        let unsolicited = resp(ListMode::Topic("joined".to_owned()));
+        assert_eq!(
+            response_poll(&unsolicited, &topic, None, joined),
+            Some("joined")
+        );
+    }
+
+    #[tokio::test]
+    async fn topic_responses_only_carry_the_requested_poll() {
+        let _storage = TempStorage::new();
+        for (poll, name) in [(TOPIC_NAME.as_str(), "Rust"), ("other", "Elm")] {
+            let vote = add_vote(poll, name, None, MIN_WEIGHT).unwrap();
+            set_visibility(poll, vote.id, true).unwrap();
+        }
+
+        let peer = PeerId::random();
+        let (sender, mut responses) = mpsc::unbounded_channel();
+        respond_with_public_votes(
+            sender,
+            TOPIC_NAME.clone(),
+            peer.to_string(),
+            ListMode::Topic("other".to_owned()),
+            ResponseTarget::Peer(peer),
+            Some("1-0".to_owned()),
+        );
+
+        let sent = responses.recv().await.expect("no response was sent");
+        // published where the request came from, about the requested poll
+        assert_eq!(sent.poll, *TOPIC_NAME);
+        assert_eq!(sent.response.mode, ListMode::Topic("other".to_owned()));
+        assert_eq!(
+            sent.response
+                .data
+                .iter()
+                .map(|r| &r.name)
+                .collect::<Vec<_>>(),
+            vec!["Elm"]
+        );
+    }
+
+    #[test]
+    fn synced_writes_replace_files_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STORAGE_FILE_NAME);
+        fs::write(&path, b"previous votes").unwrap();
+
+        write_synced(&path, b"[]").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"[]");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // a failed rename leaves no temporary file behind
+        let taken = dir.path().join("taken");
+        fs::create_dir(&taken).unwrap();
+        assert!(write_synced(&taken, b"[]").is_err());
+        assert!(taken.is_dir());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn poll_commands_are_answered_by_the_swarm_loop() {
+        let mut swarm = memory_swarm().await;
+        let (commands, mut received) = mpsc::unbounded_channel();
+        let state = SwarmState { commands };
+        let swarm_loop = tokio::spawn(async move {
+            while let Some(command) = received.recv().await {
+                let gossipsub = &mut swarm.behaviour_mut().gossipsub;
+                match command {
+                    SwarmCommand::JoinPoll { topic, reply } => {
+                        let _ = reply.send(join_or_leave_poll(gossipsub, &topic, true));
+                    }
+                    SwarmCommand::LeavePoll { topic, reply } => {
+                        let _ = reply.send(join_or_leave_poll(gossipsub, &topic, false));
+                    }
+                    _ => unreachable!("only poll commands are sent"),
+                }
+            }
+            swarm
+        });
+
+        let topic = "math".to_owned();
+        let joined = state
+            .request(|reply| SwarmCommand::JoinPoll { topic, reply })
+            .await;
+        assert_eq!(joined, Ok(Ok(())));
+        let topic = "math".to_owned();
+        let left = state
+            .request(|reply| SwarmCommand::LeavePoll { topic, reply })
+            .await;
+        assert_eq!(left, Ok(Ok(())));
+
+        drop(state);
+        let swarm = swarm_loop.await.unwrap();
+        assert_eq!(swarm.behaviour().gossipsub.topics().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn requests_fail_once_the_swarm_loop_is_gone() {
+        let (commands, received) = mpsc::unbounded_channel();
+        let state = SwarmState { commands };
+        drop(received);
+        let topic = "math".to_owned();
+        let joined = state
+            .request(|reply| SwarmCommand::JoinPoll { topic, reply })
+            .await;
+        assert!(joined.is_err());
+    }
+
+    #[test]
+    fn replacing_imports_keep_ids_unique_and_reset_the_counter() {
+        let storage = TempStorage::new();
+        let file = storage.dir.path().join("import.json");
+        let write = |votes: &[Vote]| fs::write(&file, serde_json::to_vec(votes).unwrap()).unwrap();
+
+        write(&[test_vote(4, "Go", true), test_vote(4, "Rust", true)]);
+        assert!(import_votes_from(&file, ImportMode::Replace).is_err());
+        assert!(read_local_votes().unwrap().is_empty());
+
+        write(&[test_vote(4, "Go", true), test_vote(9, "Rust", true)]);
+        assert_eq!(import_votes_from(&file, ImportMode::Replace).unwrap(), 2);
+        assert_eq!(read_poll_storage(&TOPIC_NAME).unwrap().next_id, 10);
+        assert_eq!(
+            add_vote(&TOPIC_NAME, "Elm", None, MIN_WEIGHT).unwrap().id,
+            10
+        );
+
+        write(&[test_vote(0, "Go", true), test_vote(1, "Rust", true)]);
+        assert_eq!(import_votes_from(&file, ImportMode::Replace).unwrap(), 2);
+        assert_eq!(
+            add_vote(&TOPIC_NAME, "Elm", None, MIN_WEIGHT).unwrap().id,
+            2
+        );
+    }
+
+    #[test]
+    fn rebroadcast_slots_are_taken_before_publishing_and_given_back_on_failure() {
+        let rebroadcasts = RebroadcastState::default();
+        let start = Instant::now();
+        let previous = rebroadcasts.reserve(start).unwrap();
+        assert_eq!(previous, None);
+        // a concurrent call is turned down while the first one publishes
+        assert_eq!(
+            rebroadcasts.reserve(start + Duration::from_secs(1)),
+            Err(Duration::from_secs(1))
+        );
+
+        rebroadcasts.release(previous);
+        assert_eq!(rebroadcasts.reserve(start), Ok(None));
+        assert_eq!(
+            rebroadcasts.reserve(start + REBROADCAST_INTERVAL),
+            Ok(Some(start))
+        );
+    }
+
+    #[test]
+    fn listen_addrs_cover_each_transport_and_family() {
+        let (v4, v6) = match *LOCAL_ONLY {
+            true => ("127.0.0.1", "::1"),
+            false => ("0.0.0.0", "::"),
+        };
+        let cases = [
+            (
+                TransportKind::Memory,
+                IpFamilies::V4,
+                vec!["/memory/4001".to_owned()],
+            ),
+            (
+                TransportKind::Memory,
+                IpFamilies::V6,
+                vec!["/memory/4001".to_owned()],
+            ),
+            (
+                TransportKind::Memory,
+                IpFamilies::Dual,
+                vec!["/memory/4001".to_owned()],
+            ),
+            (
+                TransportKind::Tcp,
+                IpFamilies::V4,
+                vec![format!("/ip4/{}/tcp/4001", v4)],
+            ),
+            (
+                TransportKind::Tcp,
+                IpFamilies::V6,
+                vec![format!("/ip6/{}/tcp/4001", v6)],
+            ),
+            (
+                TransportKind::Tcp,
+                IpFamilies::Dual,
+                vec![
+                    format!("/ip4/{}/tcp/4001", v4),
+                    format!("/ip6/{}/tcp/4001", v6),
+                ],
+            ),
+        ];
+        for (kind, families, expected) in cases {
+            let addrs: Vec<String> = listen_addrs(kind, families, 4001)
+                .iter()
+                .map(Multiaddr::to_string)
+                .collect();
+            assert_eq!(addrs, expected, "{:?} over {:?}", kind, families);
+        }
+    }
+
+    #[test]
+    fn backups_rotate_and_restore_by_swapping() {
+        let storage = TempStorage::new();
+        let count = backup_count();
+        let path = storage.votes_file().to_str().unwrap().to_owned();
+        let stored_id = |path: &str| parse_storage(&fs::read(path).unwrap()).unwrap().votes[0].id;
+        for id in 0..=count {
+            write_local_votes(&vec![test_vote(id, "Rust", true)]).unwrap();
+        }
+
+        assert_eq!(stored_id(&path), count);
+        for index in 1..=count {
+            assert_eq!(stored_id(&backup_path(&path, index)), count - index);
+        }
+        assert!(!Path::new(&backup_path(&path, count + 1)).exists());
+
+        let restored = restore_poll_backup(&TOPIC_NAME, 1).unwrap();
+        assert_eq!(restored[0].id, count - 1);
+        assert_eq!(stored_id(&path), count - 1);
+        assert_eq!(stored_id(&backup_path(&path, 1)), count);
+        assert!(!Path::new(&format!("{}.swap", path)).exists());
+        assert!(restore_poll_backup(&TOPIC_NAME, count + 1).is_err());
+    }
+
+    #[test]
+    fn targeted_requests_are_only_answered_by_their_target() {
+        let mut answers = Answers::new();
+        let local = PeerId::random();
+        let source = PeerId::random();
+        let for_other = ListRequest {
+            mode: ListMode::One(PeerId::random().to_string()),
+            request_id: None,
+            attempt: 0,
+        };
+        assert_eq!(answers.request(source, &for_other, &local), None);
+        let malformed = ListRequest {
+            mode: ListMode::One("not a peer id".to_owned()),
+            ..for_other
+        };
+        assert_eq!(answers.request(source, &malformed, &local), None);
+
+        let for_us = ListRequest {
+            mode: ListMode::One(local.to_string()),
+            request_id: None,
+            attempt: 0,
+        };
+        assert_eq!(
+            answers.request(source, &for_us, &local),
+            Some(Answer {
+                receiver: source.to_string(),
+                mode: ListMode::One(local.to_string()),
+                target: ResponseTarget::Peer(source),
+            })
+        );
+    }
+
+    #[test]
+    fn peers_are_only_dropped_once_every_mdns_address_expired() {
+        let mut addrs = PeerAddrs::default();
+        let peer = PeerId::random();
+        let first: Multiaddr = "/ip4/192.168.1.2/tcp/4001".parse().unwrap();
+        let second: Multiaddr = "/ip4/192.168.1.2/tcp/4002".parse().unwrap();
+        assert!(addrs.discovered(peer, first.clone()));
+        assert!(!addrs.discovered(peer, second.clone()));
+        assert!(!addrs.discovered(peer, first.clone()));
+
+        // rediscovered under another address while the first one expires
+        assert!(!addrs.expired(peer, &first));
+        assert!(!addrs.expired(peer, &first));
+        assert!(addrs.expired(peer, &second));
+        assert!(!addrs.expired(peer, &second));
+        assert!(!addrs.expired(PeerId::random(), &first));
+    }
+
+    #[test]
+    fn ids_of_deleted_votes_are_not_reused() {
+        let _storage = TempStorage::new();
+        add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT).unwrap();
+        let elm = add_vote(&TOPIC_NAME, "Elm", None, MIN_WEIGHT).unwrap();
+        assert_eq!(delete_votes_named(&TOPIC_NAME, "Elm").unwrap(), 1);
+
+        let ocaml = add_vote(&TOPIC_NAME, "OCaml", None, MIN_WEIGHT).unwrap();
+        assert_eq!(ocaml.id, elm.id + 1);
+        // the counter is stored with the votes, so a restart carries on from it too
+        assert_eq!(
+            read_poll_storage(&TOPIC_NAME).unwrap().next_id,
+            ocaml.id + 1
+        );
+    }
+
+    #[test]
+    fn reset_backs_up_then_empties_the_votes() {
+        let storage = TempStorage::new();
+        add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT).unwrap();
+        reset_local_votes().unwrap();
+
+        assert!(read_local_votes().unwrap().is_empty());
+        let backup = format!("{}.bak", storage.votes_file().display());
+        let saved = parse_storage(&fs::read(backup).unwrap()).unwrap();
+        assert_eq!(saved.votes.len(), 1);
+        assert_eq!(saved.votes[0].name, "Rust");
+    }
+
+    #[test]
+    fn visibility_is_set_both_ways_and_persisted() {
+        let _storage = TempStorage::new();
+        let rust = add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT).unwrap();
+        assert!(!rust.public);
+        let public = |id| {
+            read_local_votes()
+                .unwrap()
+                .iter()
+                .find(|r| r.id == id)
+                .unwrap()
+                .public
+        };
+
+        set_visibility(&TOPIC_NAME, rust.id, true).unwrap();
+        assert!(public(rust.id));
+        // setting it again is not an error
+        set_visibility(&TOPIC_NAME, rust.id, true).unwrap();
+        assert!(public(rust.id));
+        set_visibility(&TOPIC_NAME, rust.id, false).unwrap();
+        assert!(!public(rust.id));
+    }
+
+    #[test]
+    fn votes_are_deleted_by_language() {
+        let _storage = TempStorage::new();
+        // several votes for a language, as older versions could store
+        write_local_votes(&vec![
+            test_vote(0, "Rust", true),
+            test_vote(1, "Go", false),
+            test_vote(2, "Rust", false),
+        ])
+        .unwrap();
+
+        assert_eq!(delete_votes_named(&TOPIC_NAME, "Rust").unwrap(), 2);
+        assert_eq!(local_vote_names(), vec!["Go"]);
+        // nothing matching is not an error
+        assert_eq!(delete_votes_named(&TOPIC_NAME, "Rust").unwrap(), 0);
+        assert_eq!(delete_votes_named(&TOPIC_NAME, "OCaml").unwrap(), 0);
+        assert_eq!(local_vote_names(), vec!["Go"]);
+    }
+
+    #[test]
+    fn renamed_votes_keep_their_id_and_creation_time() {
+        let _storage = TempStorage::new();
+        let rust = add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT).unwrap();
+        add_vote(&TOPIC_NAME, "Go", None, MIN_WEIGHT).unwrap();
+
+        rename_local_vote(&TOPIC_NAME, rust.id, "Elm").unwrap();
+        let votes = read_local_votes().unwrap();
+        let renamed = votes.iter().find(|r| r.id == rust.id).unwrap();
+        assert_eq!(renamed.name, "Elm");
+        assert_eq!(renamed.created_at, rust.created_at);
+        assert_eq!(local_vote_names(), vec!["Elm", "Go"]);
+
+        // failed renames leave the vote as it was
+        assert!(rename_local_vote(&TOPIC_NAME, rust.id, "Go").is_err());
+        assert!(rename_local_vote(&TOPIC_NAME, rust.id, "Brainfuck").is_err());
+        assert_eq!(local_vote_names(), vec!["Elm", "Go"]);
+    }
+
+    #[test]
+    fn bulk_publishing_writes_once_and_returns_the_flipped_ids() {
+        let storage = TempStorage::new();
+        for name in ["Rust", "Go", "Elm"] {
+            add_vote(&TOPIC_NAME, name, None, MIN_WEIGHT).unwrap();
+        }
+        set_visibility(&TOPIC_NAME, 1, true).unwrap();
+
+        // already public and unknown ids are left out
+        assert_eq!(
+            publish_local_votes(&TOPIC_NAME, &[0, 1, 2, 42]).unwrap(),
+            vec![0, 2]
+        );
+        assert!(read_local_votes().unwrap().iter().all(|r| r.public));
+        // a single write: the latest backup is the file before the call
+        let backup = backup_path(storage.votes_file().to_str().unwrap(), 1);
+        let before: Vec<bool> = parse_storage(&fs::read(backup).unwrap())
+            .unwrap()
+            .votes
+            .iter()
+            .map(|r| r.public)
+            .collect();
+        assert_eq!(before, vec![false, true, false]);
+
+        assert!(publish_local_votes(&TOPIC_NAME, &[0, 42])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn fetches_nobody_answers_time_out_with_an_error() {
+        let timeout = Duration::from_secs(DEFAULT_QUERY_TIMEOUT_SECS);
+        let start = Instant::now();
+        let mut queries = Queries::new(timeout);
+        queries.start("1-0".to_owned(), "votes".to_owned(), (), start);
+        let (reply, answer) = oneshot::channel();
+        let mut fetch = Some(PendingFetch {
+            reply,
+            votes: vec![],
+        });
+
+        // retried once, then given up
+        let mut now = start;
+        while fetch.is_some() {
+            now += timeout;
+            for update in queries.poll_updates(now) {
+                if let QueryUpdate::Complete {
+                    request_id,
+                    peers_responded,
+                } = update
+                {
+                    fetch.take().unwrap().complete(&request_id, peers_responded);
+                }
+            }
+            assert!(now < start + timeout * 3, "the query never completed");
+        }
+        assert_eq!(answer.await.unwrap().unwrap_err(), "no answer to query 1-0");
+    }
+
+    #[test]
+    fn mdns_config_is_read_from_the_environment() {
+        let default = MdnsConfig::default();
+        env::set_var(behaviour::MDNS_QUERY_INTERVAL_ENV, "30");
+        env::set_var(behaviour::MDNS_TTL_ENV, "not a number");
+        let config = behaviour::mdns_config_from_env();
+        env::remove_var(behaviour::MDNS_QUERY_INTERVAL_ENV);
+        env::remove_var(behaviour::MDNS_TTL_ENV);
+
+        assert_eq!(config.query_interval, Duration::from_secs(30));
+        // unparsable values fall back to libp2p's default
+        assert_eq!(config.ttl, default.ttl);
+        let config = behaviour::mdns_config_from_env();
+        assert_eq!(config.query_interval, default.query_interval);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_storage_fails_with_permission_denied() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let storage = TempStorage::new();
+        add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT).unwrap();
+        // votes files are replaced rather than written in place, so it's their directory that
+        // has to be read-only
+        let set_mode = |mode| {
+            fs::set_permissions(storage.dir.path(), fs::Permissions::from_mode(mode)).unwrap()
+        };
+        set_mode(0o555);
+        if fs::write(storage.dir.path().join("probe"), b"").is_ok() {
+            // permissions aren't enforced, e.g. for root
+            set_mode(0o755);
+            return;
+        }
+
+        let added = add_vote(&TOPIC_NAME, "Go", None, MIN_WEIGHT);
+        set_mode(0o755);
+        let expected = storage.votes_file().display().to_string();
+        assert!(matches!(
+            added,
+            Err(AddVoteError::Storage(StorageError::PermissionDenied { path })) if path == expected
+        ));
+        assert_eq!(local_vote_names(), vec!["Rust"]);
+    }
+
+    #[tokio::test]
+    async fn a_request_and_its_response_move_the_counters() {
+        let poll = "metrics-test";
+        let topic = IdentTopic::new(poll);
+        let mut asking = memory_swarm().await;
+        let mut answering = memory_swarm().await;
+        asking.behaviour_mut().gossipsub.subscribe(&topic).unwrap();
+        answering
+            .behaviour_mut()
+            .gossipsub
+            .subscribe(&topic)
+            .unwrap();
+        asking.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let (asked, answered) = (Counters::default(), Counters::default());
+        let mut seen = RecentKeys::new(Duration::from_secs(60));
+        let request = ListRequest {
+            mode: ListMode::ALL,
+            request_id: Some("1-0".to_owned()),
+            attempt: 0,
+        };
+        let response = broadcast_response(vec![test_vote(0, "Rust", true)]);
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                tokio::select! {
+                    event = asking.select_next_some() => match event {
+                        SwarmEvent::NewListenAddr { address, .. } => {
+                            answering.dial_addr(address).unwrap()
+                        }
+                        SwarmEvent::Behaviour(behaviour::BehaviourEvent::GossipsubEvent(
+                            GossipsubEvent::Subscribed { .. },
+                        )) => {
+                            let payload = WireFormat::Json.encode(&request).unwrap();
+                            let gossipsub = &mut asking.behaviour_mut().gossipsub;
+                            publish_counted(gossipsub, &asked.requests_sent, poll, &payload, false)
+                                .unwrap();
+                        }
+                        SwarmEvent::Behaviour(behaviour::BehaviourEvent::GossipsubEvent(
+                            GossipsubEvent::Message { message, .. },
+                        )) => {
+                            let source = message.source.unwrap();
+                            asked.received(&receive(&mut seen, &source, &message.data));
+                            break;
+                        }
+                        _ => {}
+                    },
+                    event = answering.select_next_some() => {
+                        if let SwarmEvent::Behaviour(behaviour::BehaviourEvent::GossipsubEvent(
+                            GossipsubEvent::Message { message, .. },
+                        )) = event
+                        {
+                            let source = message.source.unwrap();
+                            let received = receive(&mut seen, &source, &message.data);
+                            assert!(matches!(
+                                received,
+                                Received::Parsed(Some(IncomingMessage::Request(_)))
+                            ));
+                            answered.received(&received);
+                            // delivered again, e.g. through another peer
+                            answered.received(&receive(&mut seen, &source, &message.data));
+                            let payload = WireFormat::Json.encode(&response).unwrap();
+                            let gossipsub = &mut answering.behaviour_mut().gossipsub;
+                            publish_counted(gossipsub, &answered.responses_sent, poll, &payload, false)
+                                .unwrap();
+                        }
+                    }
+                }
+            }
         })
-        .invoke_handler(tauri::generate_handler![on_publish_vote])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .await
+        .expect("no response received in time");
+
+        let asked = asked.snapshot();
+        assert_eq!(asked.requests_sent, 1);
+        assert_eq!(asked.messages_received, 1);
+        assert_eq!(asked.responses_sent, 0);
+        let answered = answered.snapshot();
+        assert_eq!(answered.responses_sent, 1);
+        assert_eq!(answered.messages_received, 2);
+        assert_eq!(answered.duplicates_dropped, 1);
+        assert_eq!(answered.requests_sent, 0);
+        assert_eq!(answered.invalid_messages, 0);
+    }
+
+    #[tokio::test]
+    async fn connected_subscribers_are_listed_per_poll() {
+        let topic = IdentTopic::new("subscribers-test");
+        let mut listening = memory_swarm().await;
+        let mut dialing = memory_swarm().await;
+        listening
+            .behaviour_mut()
+            .gossipsub
+            .subscribe(&topic)
+            .unwrap();
+        dialing.behaviour_mut().gossipsub.subscribe(&topic).unwrap();
+        listening.listen_on("/memory/0".parse().unwrap()).unwrap();
+        assert!(
+            subscribed_peers_of(&listening.behaviour().gossipsub, "subscribers-test").is_empty()
+        );
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                tokio::select! {
+                    event = listening.select_next_some() => match event {
+                        SwarmEvent::NewListenAddr { address, .. } => {
+                            dialing.dial_addr(address).unwrap()
+                        }
+                        SwarmEvent::Behaviour(behaviour::BehaviourEvent::GossipsubEvent(
+                            GossipsubEvent::Subscribed { .. },
+                        )) => break,
+                        _ => {}
+                    },
+                    _ = dialing.select_next_some() => {}
+                }
+            }
+        })
+        .await
+        .expect("no subscription seen in time");
+
+        let gossipsub = &listening.behaviour().gossipsub;
+        assert_eq!(
+            subscribed_peers_of(gossipsub, "subscribers-test"),
+            vec![dialing.local_peer_id().to_string()]
+        );
+        assert!(subscribed_peers_of(gossipsub, "other-poll").is_empty());
+    }
+
+    #[test]
+    fn transports_build_for_every_handshake_pattern() {
+        assert_eq!("XX".parse(), Ok(HandshakePattern::XX));
+        assert_eq!("ix".parse(), Ok(HandshakePattern::IX));
+        assert!("ik".parse::<HandshakePattern>().is_err());
+
+        let keys = identity::Keypair::generate_ed25519();
+        for kind in [TransportKind::Tcp, TransportKind::Memory] {
+            for pattern in [HandshakePattern::XX, HandshakePattern::IX] {
+                build_transport(&keys, kind, pattern);
+            }
+        }
+    }
+
+    #[test]
+    fn merging_imports_write_and_notify_once() {
+        let storage = TempStorage::new();
+        add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT).unwrap();
+        let file = storage.dir.path().join("import.json");
+        let votes = vec![
+            test_vote(0, "Go", true),
+            test_vote(1, "Elm", true),
+            test_vote(2, "OCaml", false),
+        ];
+        fs::write(&file, serde_json::to_vec(&votes).unwrap()).unwrap();
+        let mut updates = VOTES_WATCH.subscribe();
+        updates.borrow_and_update();
+
+        assert_eq!(import_votes_from(&file, ImportMode::Merge).unwrap(), 3);
+        // a single write: the latest backup holds the votes from before the import
+        let backup = backup_path(storage.votes_file().to_str().unwrap(), 1);
+        let before = parse_storage(&fs::read(backup).unwrap()).unwrap().votes;
+        assert_eq!(before.len(), 1);
+        assert!(updates.has_changed().unwrap());
+        let update = updates.borrow_and_update().clone().unwrap();
+        assert_eq!(update.votes.len(), 4);
+    }
+
+    #[test]
+    fn audit_entries_are_appended() {
+        let _storage = TempStorage::new();
+        let path = get_data_file_path(AUDIT_FILE_NAME).unwrap();
+        let rust = add_vote(&TOPIC_NAME, "Rust", None, MIN_WEIGHT).unwrap();
+        let first = fs::read_to_string(&path).unwrap();
+        assert_eq!(first.lines().count(), 1);
+
+        set_visibility(&TOPIC_NAME, rust.id, true).unwrap();
+        delete_votes_named(&TOPIC_NAME, "Rust").unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        // earlier lines are left as they were
+        assert!(content.starts_with(&first));
+        assert_eq!(content.lines().count(), 3);
+
+        let entries = read_audit_entries(2).unwrap();
+        assert!(matches!(
+            entries.as_slice(),
+            [
+                AuditEvent {
+                    operation: AuditOperation::Publish,
+                    ..
+                },
+                AuditEvent {
+                    operation: AuditOperation::Delete,
+                    ..
+                },
+            ]
+        ));
+        assert!(entries.iter().all(|e| e.vote_id == Some(rust.id)));
+        assert_eq!(entries[1].name.as_deref(), Some("Rust"));
+    }
+
+    #[test]
+    fn signed_votes_are_smaller_in_cbor_and_round_trip_in_both_formats() {
+        let keys = identity::Keypair::generate_ed25519();
+        let votes: Votes = ["Rust", "Go", "Elm", "OCaml"]
+            .iter()
+            .enumerate()
+            .map(|(id, name)| sign_vote(&keys, test_vote(id, name, true)).unwrap())
+            .collect();
+        let resp = broadcast_response(votes.clone());
+        let json = WireFormat::Json.encode(&resp).unwrap();
+        let cbor = WireFormat::Cbor.encode(&resp).unwrap();
+        assert!(cbor.len() < json.len(), "{} >= {}", cbor.len(), json.len());
+
+        for (format, data) in [(WireFormat::Json, json), (WireFormat::Cbor, cbor)] {
+            let decoded: ListResponse = format.decode(&data).unwrap();
+            assert_eq!(decoded.data.len(), votes.len());
+            for (decoded, vote) in decoded.data.iter().zip(&votes) {
+                assert_eq!(decoded.name, vote.name);
+                assert_eq!(decoded.signature, vote.signature);
+            }
+        }
+
+        // JSON signatures are still arrays of numbers, as older peers wrote and expect them
+        let json = serde_json::to_value(&votes[0]).unwrap();
+        assert!(json["signature"].is_array());
+        let parsed: Vote = serde_json::from_value(json).unwrap();
+        assert!(verify_vote(&parsed, &PeerId::from(keys.public())));
+    }
+
+    #[test]
+    fn initial_sync_counts_its_responses_until_its_query_ends() {
+        let mut sync = Some(InitialSync::new("1-0".to_owned()));
+        let (first, second) = (PeerId::random(), PeerId::random());
+        assert!(InitialSync::answered_by(&mut sync, None).is_none());
+        assert!(InitialSync::answered_by(&mut sync, Some("2-0")).is_none());
+        for (peer, merged) in [(first, 2), (first, 1), (second, 0)] {
+            InitialSync::answered_by(&mut sync, Some("1-0"))
+                .unwrap()
+                .record(peer, merged);
+        }
+        let progress = json!({ "peers_responded": 2, "votes_merged": 3 });
+        assert_eq!(sync.as_ref().unwrap().progress(), progress);
+
+        // other queries completing leave it running
+        assert!(InitialSync::finish(&mut sync, "2-0").is_none());
+        let completed = InitialSync::finish(&mut sync, "1-0").unwrap();
+        assert_eq!(completed.progress(), progress);
+        assert!(sync.is_none());
+        assert!(InitialSync::answered_by(&mut sync, Some("1-0")).is_none());
+    }
+
+    #[test]
+    fn future_dated_remote_votes_are_clamped_on_merge() {
+        let _storage = TempStorage::new();
+        let source = PeerId::random().to_string();
+        let before = now_millis();
+        let skew = DEFAULT_MAX_FUTURE_SKEW_SECS * 1000;
+        let dated = |id, created_at| Vote {
+            created_at,
+            ..test_vote(id, "Rust", true)
+        };
+        let votes = vec![
+            dated(0, before + 60 * 60 * 1000),
+            // within the allowed skew
+            dated(1, before + skew / 2),
+            dated(2, before - 1000),
+        ];
+
+        let added = merge_remote_votes(&TOPIC_NAME, &source, votes).unwrap();
+        let after = now_millis();
+        assert!((before..=after).contains(&added[0].created_at));
+        assert_eq!(added[1].created_at, before + skew / 2);
+        assert_eq!(added[2].created_at, before - 1000);
+        let stored = &read_remote_votes(&TOPIC_NAME).unwrap()[&source];
+        assert!((before..=after).contains(&stored[0].created_at));
+    }
+
+    #[tokio::test]
+    async fn dry_runs_publish_nothing() {
+        let mut swarm = memory_swarm().await;
+        let gossipsub = &mut swarm.behaviour_mut().gossipsub;
+        let sent = AtomicU64::new(0);
+        let payload = WireFormat::Json
+            .encode(&broadcast_response(vec![]))
+            .unwrap();
+
+        assert_eq!(
+            publish_counted(gossipsub, &sent, "dry-run-test", &payload, true),
+            Ok(())
+        );
+        assert_eq!(sent.load(Ordering::Relaxed), 0);
+        // gossipsub itself refuses to publish without peers, so it was never asked to
+        assert!(publish_counted(gossipsub, &sent, "dry-run-test", &payload, false).is_err());
+        assert_eq!(sent.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn offline_nodes_leave_the_default_poll_and_answer_nobody() {
+        let mut swarm = memory_swarm().await;
+        let gossipsub = &mut swarm.behaviour_mut().gossipsub;
+        let joined = |gossipsub: &Gossipsub| gossipsub.topics().any(|t| *t == TOPIC.hash());
+
+        join_or_leave_poll(gossipsub, &TOPIC_NAME, true).unwrap();
+        assert!(joined(gossipsub));
+        // going offline
+        join_or_leave_poll(gossipsub, &TOPIC_NAME, false).unwrap();
+        assert!(!joined(gossipsub));
+        assert!(!answers_peers(true, false));
+        // and back online
+        join_or_leave_poll(gossipsub, &TOPIC_NAME, true).unwrap();
+        assert!(joined(gossipsub));
+        assert!(answers_peers(false, false));
+    }
+
+    #[test]
+    fn concurrent_publishes_of_different_votes_both_persist() {
+        let _storage = TempStorage::new();
+        let ids: Vec<usize> = ["Rust", "Go"]
+            .iter()
+            .map(|name| add_vote(&TOPIC_NAME, name, None, MIN_WEIGHT).unwrap().id)
+            .collect();
+        let start = Arc::new(std::sync::Barrier::new(ids.len()));
+        ids.iter()
+            .map(|&id| {
+                let start = start.clone();
+                std::thread::spawn(move || {
+                    start.wait();
+                    set_visibility(&TOPIC_NAME, id, true)
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .for_each(|publish| publish.join().unwrap().unwrap());
+
+        // without the storage lock, the last write would drop the other change
+        assert!(read_local_votes().unwrap().iter().all(|r| r.public));
+    }
+
+    #[test]
+    fn observers_change_nothing_but_still_take_in_votes() {
+        let _storage = TempStorage::new();
+        assert!(check_can_change_votes(true).is_err());
+        assert!(check_can_change_votes(false).is_ok());
+        // nor do they answer peers, even online
+        assert!(!answers_peers(false, true));
+
+        // responses are still stored, and votes read
+        let source = PeerId::random().to_string();
+        merge_remote_votes(&TOPIC_NAME, &source, vec![test_vote(0, "Rust", true)]).unwrap();
+        assert_eq!(read_remote_votes(&TOPIC_NAME).unwrap()[&source].len(), 1);
+        assert!(get_votes_sorted().unwrap().is_empty());
+    }
+
+    #[test]
+    fn presences_label_their_author() {
+        let mut roster = Roster::new(presence_interval());
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let presence = |peer: &PeerId, name: &str| Presence {
+            peer_id: peer.to_string(),
+            timestamp: 1_000,
+            display_name: Some(name.to_owned()),
+        };
+
+        assert!(record_presence(
+            &mut roster,
+            alice,
+            &presence(&alice, " alice\n"),
+            1_000
+        ));
+        assert_eq!(roster.label(&alice), "alice");
+        // relayed by another peer, so it can't be trusted
+        assert!(!record_presence(
+            &mut roster,
+            bob,
+            &presence(&alice, "mallory"),
+            1_000
+        ));
+        assert_eq!(roster.label(&alice), "alice");
+        assert_eq!(roster.label(&bob), presence::short_peer_id(&bob));
+    }
+
+    #[test]
+    fn polls_with_similar_names_use_different_files() {
+        assert_eq!(poll_file_stem("math-101"), "math-101");
+        assert_eq!(poll_file_stem("math 101"), "math_20101");
+        assert_eq!(poll_file_stem("math_101"), "math_5f101");
+        assert_eq!(poll_file_stem("café"), "caf_c3_a9");
+        assert_eq!(poll_file_stem("../x"), "_2e_2e_2fx");
+    }
 }
@@ -0,0 +1,120 @@
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+/// A peer address seen through mDNS
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerEntry {
+    pub peer_id: String,
+    pub address: String,
+    /// Unix timestamp, in seconds
+    pub last_seen: u64,
+}
+
+/// Recently seen peer addresses, least recently seen first
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PeerStore {
+    entries: Vec<PeerEntry>,
+}
+
+impl PeerStore {
+    /// Marks `address` as just seen, dropping the least recently seen entries past `capacity`
+    pub fn record(&mut self, peer_id: &PeerId, address: &Multiaddr, now: u64, capacity: usize) {
+        let peer_id = peer_id.to_string();
+        let address = address.to_string();
+        self.entries
+            .retain(|e| !(e.peer_id == peer_id && e.address == address));
+        self.entries.push(PeerEntry {
+            peer_id,
+            address,
+            last_seen: now,
+        });
+        let excess = self.entries.len().saturating_sub(capacity);
+        self.entries.drain(..excess);
+    }
+
+    /// Drops entries not seen for more than `max_age` seconds
+    pub fn prune(&mut self, max_age: u64, now: u64) {
+        self.entries
+            .retain(|e| now.saturating_sub(e.last_seen) <= max_age);
+    }
+
+    /// The `count` most recently seen addresses, completed with their peer id so they can be
+    /// dialed as is. Entries that no longer parse are skipped.
+    pub fn most_recent(&self, count: usize) -> Vec<Multiaddr> {
+        self.entries
+            .iter()
+            .rev()
+            .filter_map(|e| {
+                let peer_id = e.peer_id.parse::<PeerId>().ok()?;
+                let address = e.address.parse::<Multiaddr>().ok()?;
+                Some(address.with(Protocol::P2p(peer_id.into())))
+            })
+            .take(count)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> Multiaddr {
+        format!("/ip4/192.168.1.20/tcp/{}", port).parse().unwrap()
+    }
+
+    #[test]
+    fn most_recent_addresses_come_first_with_their_peer_id() {
+        let peer = PeerId::random();
+        let mut store = PeerStore::default();
+        store.record(&peer, &addr(1), 100, 10);
+        store.record(&peer, &addr(2), 200, 10);
+        // seen again, moves to the front
+        store.record(&peer, &addr(1), 300, 10);
+
+        let recent = store.most_recent(10);
+        assert_eq!(
+            recent,
+            vec![
+                addr(1).with(Protocol::P2p(peer.into())),
+                addr(2).with(Protocol::P2p(peer.into())),
+            ]
+        );
+        assert_eq!(store.most_recent(1).len(), 1);
+    }
+
+    #[test]
+    fn least_recently_seen_entries_are_dropped_past_capacity() {
+        let peer = PeerId::random();
+        let mut store = PeerStore::default();
+        for port in 1..=5 {
+            store.record(&peer, &addr(port), u64::from(port), 3);
+        }
+        assert_eq!(
+            store.most_recent(10),
+            (3..=5)
+                .rev()
+                .map(|port| addr(port).with(Protocol::P2p(peer.into())))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn old_and_unparsable_entries_are_skipped() {
+        let peer = PeerId::random();
+        let mut store = PeerStore::default();
+        store.record(&peer, &addr(1), 100, 10);
+        store.record(&peer, &addr(2), 1000, 10);
+        store.entries.push(PeerEntry {
+            peer_id: "not-a-peer-id".to_owned(),
+            address: addr(3).to_string(),
+            last_seen: 1000,
+        });
+
+        store.prune(500, 1000);
+        assert_eq!(store.entries.len(), 2);
+        assert_eq!(
+            store.most_recent(10),
+            vec![addr(2).with(Protocol::P2p(peer.into()))]
+        );
+    }
+}
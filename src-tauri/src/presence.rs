@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use libp2p::PeerId;
+use serde::Serialize;
+
+/// How long a peer stays online without a heartbeat, in heartbeat intervals
+pub const MISSED_HEARTBEATS: u32 = 3;
+/// Longest display name kept, in characters
+pub const MAX_LABEL_CHARS: usize = 32;
+
+/// A peer heard from recently
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeerInfo {
+    pub peer_id: String,
+    /// Unix timestamp of its last heartbeat, in milliseconds
+    pub last_seen: u64,
+    pub label: String,
+}
+
+/// What to show for a peer without a display name, peer ids all starting the same way
+pub fn short_peer_id(peer: &PeerId) -> String {
+    let id = peer.to_string();
+    format!("…{}", &id[id.len().saturating_sub(6)..])
+}
+
+/// Trims a display name sent by a peer, dropping empty ones and control characters
+pub fn sanitize_label(label: &str) -> Option<String> {
+    let label: String = label
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_LABEL_CHARS)
+        .collect();
+    let label = label.trim();
+    (!label.is_empty()).then(|| label.to_owned())
+}
+
+/// Last heartbeat of each peer, timestamps being unix milliseconds
+pub struct Roster {
+    ttl: u64,
+    last_seen: HashMap<PeerId, u64>,
+    /// Display names, kept after a peer goes offline
+    labels: HashMap<PeerId, String>,
+}
+
+impl Roster {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            ttl: (interval * MISSED_HEARTBEATS).as_millis() as u64,
+            last_seen: HashMap::new(),
+            labels: HashMap::new(),
+        }
+    }
+
+    /// Records a heartbeat, returns whether the peer just came online or changed its name
+    pub fn record(&mut self, peer: PeerId, now: u64, label: Option<String>) -> bool {
+        let renamed = match label {
+            Some(label) => self.labels.insert(peer, label.clone()).as_ref() != Some(&label),
+            None => false,
+        };
+        self.last_seen.insert(peer, now).is_none() || renamed
+    }
+
+    /// Peers with a heartbeat not expired yet
+    pub fn peers(&self) -> impl Iterator<Item = &PeerId> + '_ {
+        self.last_seen.keys()
+    }
+
+    /// Display name of `peer`, or a shortened peer id
+    pub fn label(&self, peer: &PeerId) -> String {
+        self.labels
+            .get(peer)
+            .cloned()
+            .unwrap_or_else(|| short_peer_id(peer))
+    }
+
+    /// Forgets peers not heard from in time, returning them
+    pub fn expire(&mut self, now: u64) -> Vec<PeerId> {
+        let ttl = self.ttl;
+        let expired: Vec<PeerId> = self
+            .last_seen
+            .iter()
+            .filter(|(_, seen)| now.saturating_sub(**seen) > ttl)
+            .map(|(peer, _)| *peer)
+            .collect();
+        for peer in &expired {
+            self.last_seen.remove(peer);
+        }
+        expired
+    }
+
+    /// Peers still online at `now`, most recently heard from first
+    pub fn online(&self, now: u64) -> Vec<PeerInfo> {
+        let mut online: Vec<PeerInfo> = self
+            .last_seen
+            .iter()
+            .filter(|(_, seen)| now.saturating_sub(**seen) <= self.ttl)
+            .map(|(peer, seen)| PeerInfo {
+                peer_id: peer.to_string(),
+                last_seen: *seen,
+                label: self.label(peer),
+            })
+            .collect();
+        online.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+        online
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_secs(10);
+    /// `MISSED_HEARTBEATS` intervals, in milliseconds
+    const TTL: u64 = 30_000;
+
+    #[test]
+    fn peers_expire_after_missed_heartbeats() {
+        let mut roster = Roster::new(INTERVAL);
+        let quiet = PeerId::random();
+        let chatty = PeerId::random();
+        assert!(roster.record(quiet, 1_000, None));
+        assert!(roster.record(chatty, 1_000, None));
+        assert!(!roster.record(chatty, 20_000, None));
+
+        assert!(roster.expire(1_000 + TTL).is_empty());
+        assert_eq!(roster.expire(1_001 + TTL), vec![quiet]);
+        assert_eq!(roster.peers().collect::<Vec<_>>(), vec![&chatty]);
+        // coming back counts as coming online
+        assert!(roster.record(quiet, 40_000, None));
+    }
+
+    #[test]
+    fn online_peers_are_most_recent_first() {
+        let mut roster = Roster::new(INTERVAL);
+        let first = PeerId::random();
+        let second = PeerId::random();
+        roster.record(first, 1_000, None);
+        roster.record(second, 2_000, Some("bob".to_owned()));
+
+        let online = roster.online(2_000);
+        assert_eq!(
+            online.iter().map(|p| p.label.as_str()).collect::<Vec<_>>(),
+            vec!["bob", short_peer_id(&first).as_str()]
+        );
+        assert!(roster.online(2_001 + TTL).is_empty());
+    }
+
+    #[test]
+    fn renames_are_reported_and_labels_kept_offline() {
+        let mut roster = Roster::new(INTERVAL);
+        let peer = PeerId::random();
+        assert!(roster.record(peer, 1_000, Some("alice".to_owned())));
+        assert!(!roster.record(peer, 2_000, Some("alice".to_owned())));
+        assert!(roster.record(peer, 3_000, Some("alice2".to_owned())));
+
+        roster.expire(4_000 + TTL);
+        assert_eq!(roster.label(&peer), "alice2");
+    }
+
+    #[test]
+    fn labels_are_sanitized() {
+        assert_eq!(sanitize_label("  alice\n"), Some("alice".to_owned()));
+        assert_eq!(sanitize_label("a\u{7}b"), Some("ab".to_owned()));
+        assert_eq!(sanitize_label(" \t "), None);
+        assert_eq!(
+            sanitize_label(&"x".repeat(100)).map(|l| l.chars().count()),
+            Some(MAX_LABEL_CHARS)
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+
+/// How long a query waits for a first response before being retried, then given up
+pub const DEFAULT_QUERY_TIMEOUT_SECS: u64 = 5;
+/// Once a peer answered, the query completes after this long without new responses
+pub const QUIET_PERIOD: Duration = Duration::from_secs(1);
+
+/// A request published to peers and still waiting for responses
+struct Query<R> {
+    poll: String,
+    request: R,
+    started: Instant,
+    last_response: Option<Instant>,
+    responders: HashSet<PeerId>,
+    retried: bool,
+}
+
+/// What the swarm loop has to do about outstanding queries
+#[derive(Debug, PartialEq)]
+pub enum QueryUpdate<R> {
+    /// Nobody answered in time, publish the request again
+    Retry {
+        request_id: String,
+        poll: String,
+        request: R,
+    },
+    /// Responses stopped arriving, or the query timed out
+    Complete {
+        request_id: String,
+        peers_responded: usize,
+    },
+}
+
+/// Outstanding queries keyed by request id, `R` being the request to send again on retry
+pub struct Queries<R> {
+    timeout: Duration,
+    queries: HashMap<String, Query<R>>,
+}
+
+impl<R: Clone> Queries<R> {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            queries: HashMap::new(),
+        }
+    }
+
+    pub fn start(&mut self, request_id: String, poll: String, request: R, now: Instant) {
+        self.queries.insert(
+            request_id,
+            Query {
+                poll,
+                request,
+                started: now,
+                last_response: None,
+                responders: HashSet::new(),
+                retried: false,
+            },
+        );
+    }
+
+    /// Returns `false` when `request_id` isn't an outstanding query
+    pub fn record_response(&mut self, request_id: &str, peer: PeerId, now: Instant) -> bool {
+        match self.queries.get_mut(request_id) {
+            Some(query) => {
+                query.last_response = Some(now);
+                query.responders.insert(peer);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The request sent for `request_id`, `None` when it isn't an outstanding query
+    pub fn request(&self, request_id: &str) -> Option<&R> {
+        self.queries.get(request_id).map(|query| &query.request)
+    }
+
+    /// Forgets `request_id`, returning `false` when it wasn't an outstanding query
+    pub fn cancel(&mut self, request_id: &str) -> bool {
+        self.queries.remove(request_id).is_some()
+    }
+
+    pub fn poll_updates(&mut self, now: Instant) -> Vec<QueryUpdate<R>> {
+        let timeout = self.timeout;
+        let mut updates = vec![];
+        self.queries.retain(|request_id, query| {
+            let timed_out = now.duration_since(query.started) >= timeout;
+            match query.last_response {
+                None if timed_out && !query.retried => {
+                    query.retried = true;
+                    query.started = now;
+                    updates.push(QueryUpdate::Retry {
+                        request_id: request_id.clone(),
+                        poll: query.poll.clone(),
+                        request: query.request.clone(),
+                    });
+                    true
+                }
+                Some(last) if timed_out || now.duration_since(last) >= QUIET_PERIOD => {
+                    updates.push(QueryUpdate::Complete {
+                        request_id: request_id.clone(),
+                        peers_responded: query.responders.len(),
+                    });
+                    false
+                }
+                None if timed_out => {
+                    updates.push(QueryUpdate::Complete {
+                        request_id: request_id.clone(),
+                        peers_responded: 0,
+                    });
+                    false
+                }
+                _ => true,
+            }
+        });
+        updates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn started(now: Instant) -> Queries<&'static str> {
+        let mut queries = Queries::new(TIMEOUT);
+        queries.start("1-0".to_owned(), "votes".to_owned(), "ALL", now);
+        queries
+    }
+
+    #[test]
+    fn silent_queries_are_retried_once_then_given_up() {
+        let start = Instant::now();
+        let mut queries = started(start);
+        assert!(queries.poll_updates(start + TIMEOUT / 2).is_empty());
+
+        let retried = start + TIMEOUT;
+        assert_eq!(
+            queries.poll_updates(retried),
+            vec![QueryUpdate::Retry {
+                request_id: "1-0".to_owned(),
+                poll: "votes".to_owned(),
+                request: "ALL",
+            }]
+        );
+        // the timeout starts over with the retry
+        assert!(queries.poll_updates(retried + TIMEOUT / 2).is_empty());
+        assert_eq!(
+            queries.poll_updates(retried + TIMEOUT),
+            vec![QueryUpdate::Complete {
+                request_id: "1-0".to_owned(),
+                peers_responded: 0,
+            }]
+        );
+        assert!(queries.poll_updates(retried + TIMEOUT * 2).is_empty());
+    }
+
+    #[test]
+    fn answered_queries_complete_once_responses_stop() {
+        let start = Instant::now();
+        let mut queries = started(start);
+        let first = PeerId::random();
+        let second = PeerId::random();
+        assert!(queries.record_response("1-0", first, start));
+        assert!(queries.record_response("1-0", first, start + QUIET_PERIOD / 2));
+        assert!(queries.record_response("1-0", second, start + QUIET_PERIOD / 2));
+
+        assert!(queries.poll_updates(start + QUIET_PERIOD).is_empty());
+        assert_eq!(
+            queries.poll_updates(start + QUIET_PERIOD * 2),
+            vec![QueryUpdate::Complete {
+                request_id: "1-0".to_owned(),
+                peers_responded: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn unknown_and_cancelled_queries_drop_responses() {
+        let start = Instant::now();
+        let mut queries = started(start);
+        assert!(!queries.record_response("2-0", PeerId::random(), start));
+
+        assert!(queries.cancel("1-0"));
+        assert!(!queries.cancel("1-0"));
+        assert!(!queries.record_response("1-0", PeerId::random(), start));
+        assert!(queries.poll_updates(start + TIMEOUT).is_empty());
+    }
+
+    #[test]
+    fn requests_of_outstanding_queries_are_known() {
+        let now = Instant::now();
+        let mut queries = started(now);
+        assert_eq!(queries.request("1-0"), Some(&"ALL"));
+        assert_eq!(queries.request("2-0"), None);
+        queries.cancel("1-0");
+        assert_eq!(queries.request("1-0"), None);
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use libp2p::Multiaddr;
+
+/// Delay before the first redial of a lost bootstrap peer, doubled on each failed attempt
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest delay between two redials
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Delay before redial number `attempt`, counting from 0
+pub fn backoff_delay(attempt: u32) -> Duration {
+    INITIAL_BACKOFF
+        .checked_mul(2u32.saturating_pow(attempt))
+        .map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF))
+}
+
+#[derive(Default)]
+struct Backoff {
+    attempt: u32,
+    next_dial: Option<Instant>,
+}
+
+/// Redial schedule of the bootstrap peers, which are the only ones we can't rediscover
+pub struct Reconnects {
+    addrs: HashMap<Multiaddr, Backoff>,
+}
+
+impl Reconnects {
+    pub fn new(addrs: impl IntoIterator<Item = Multiaddr>) -> Self {
+        Self {
+            addrs: addrs
+                .into_iter()
+                .map(|addr| (addr, Backoff::default()))
+                .collect(),
+        }
+    }
+
+    pub fn connected(&mut self, addr: &Multiaddr) {
+        if let Some(backoff) = self.addrs.get_mut(addr) {
+            *backoff = Backoff::default();
+        }
+    }
+
+    /// Schedules a redial of `addr` after a connection loss or a failed dial. Returns the
+    /// attempt number and its delay, or `None` when `addr` isn't a bootstrap peer or a redial
+    /// is already scheduled.
+    pub fn disconnected(&mut self, addr: &Multiaddr, now: Instant) -> Option<(u32, Duration)> {
+        let backoff = self.addrs.get_mut(addr)?;
+        if backoff.next_dial.is_some() {
+            return None;
+        }
+        let attempt = backoff.attempt;
+        let delay = backoff_delay(attempt);
+        backoff.attempt = attempt.saturating_add(1);
+        backoff.next_dial = Some(now + delay);
+        Some((attempt, delay))
+    }
+
+    /// Addresses to redial now
+    pub fn due(&mut self, now: Instant) -> Vec<Multiaddr> {
+        self.addrs
+            .iter_mut()
+            .filter(|(_, backoff)| backoff.next_dial.map_or(false, |at| at <= now))
+            .map(|(addr, backoff)| {
+                backoff.next_dial = None;
+                addr.clone()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_max() {
+        assert_eq!(backoff_delay(0), INITIAL_BACKOFF);
+        assert_eq!(backoff_delay(1), INITIAL_BACKOFF * 2);
+        assert_eq!(backoff_delay(3), INITIAL_BACKOFF * 8);
+        assert_eq!(backoff_delay(6), MAX_BACKOFF);
+        assert_eq!(backoff_delay(40), MAX_BACKOFF);
+        assert_eq!(backoff_delay(u32::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn redials_are_scheduled_once_and_reset_on_connection() {
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/4001".parse().unwrap();
+        let mut reconnects = Reconnects::new(vec![addr.clone()]);
+        let now = Instant::now();
+
+        assert_eq!(
+            reconnects.disconnected(&addr, now),
+            Some((0, INITIAL_BACKOFF))
+        );
+        // already scheduled
+        assert_eq!(reconnects.disconnected(&addr, now), None);
+        assert!(reconnects.due(now).is_empty());
+        assert_eq!(reconnects.due(now + INITIAL_BACKOFF), vec![addr.clone()]);
+
+        assert_eq!(
+            reconnects.disconnected(&addr, now),
+            Some((1, INITIAL_BACKOFF * 2))
+        );
+        reconnects.due(now + MAX_BACKOFF);
+        reconnects.connected(&addr);
+        assert_eq!(
+            reconnects.disconnected(&addr, now),
+            Some((0, INITIAL_BACKOFF))
+        );
+    }
+
+    #[test]
+    fn only_bootstrap_peers_are_redialed() {
+        let mut reconnects = Reconnects::new(Vec::<Multiaddr>::new());
+        let other: Multiaddr = "/ip4/10.0.0.2/tcp/4001".parse().unwrap();
+        assert_eq!(reconnects.disconnected(&other, Instant::now()), None);
+    }
+}
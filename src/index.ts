@@ -19,6 +19,9 @@ if (window.__TAURI_IPC__) {
     console.log(e);
     
   });
+  invoke<{ name: string }[]>("get_languages").then((languages) => {
+    app.ports.getLanguages.send(languages.map(({ name }) => ({ name })));
+  });
   appWindow.listen("get_languages", (e: Event<{
     languages: {
       name: string